@@ -0,0 +1,235 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, timeout};
+
+use crate::codec::FrameDecoder;
+use crate::config::ServerConfig;
+use crate::constants::BTimeout;
+use crate::errors::BError;
+use crate::lockout::{self, RateLimit};
+use crate::logger::{self, LogContext, TASK_CONTEXT};
+use crate::messages::{ClientMessage, ServerMessage};
+use crate::observer::Observer;
+use crate::shutdown::{SessionGuard, Shutdown, Verdict};
+use crate::state_machine::{BState, PRes};
+
+/// How often the accept loop checks for a shutdown request
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// Accepts connections and runs every session as a task.
+/// At most `config.max_sessions` sessions run at once,
+/// new connections are not accepted until a session finishes.
+/// After a shutdown is requested, waits for the sessions to finish.
+pub async fn serve(config: Arc<ServerConfig>, shutdown: Arc<Shutdown>) -> io::Result<()> {
+    let listener = TcpListener::bind(config.addr()).await?;
+    let sessions = Arc::new(Semaphore::new(config.max_sessions));
+
+    info!("Listening on {} (async, max {} sessions)", config.addr(), config.max_sessions);
+    let mut rate = RateLimit::new(config.connection_rate);
+
+    while !shutdown.is_requested() {
+        let permit = tokio::select! {
+            permit = Arc::clone(&sessions).acquire_owned() =>
+                permit.expect("The session semaphore is never closed"),
+            _ = sleep(ACCEPT_POLL) => continue,
+        };
+
+        let (stream, peer) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept a connection: {}", e);
+                    continue;
+                }
+            },
+            _ = sleep(ACCEPT_POLL) => continue,
+        };
+        if lockout::reject_connection(&mut rate, peer) {
+            continue;
+        }
+
+        let config = Arc::clone(&config);
+        let mut session = shutdown.track();
+        let context = LogContext::new(Some(peer));
+        tokio::spawn(TASK_CONTEXT.scope(context, async move {
+            info!("Connection established!");
+            handle_server(stream, &config, &mut session).await;
+            info!("Connection closed!");
+            drop(permit);
+        }));
+    }
+
+    drop(listener);
+    info!("Shutting down, waiting for {} sessions", shutdown.active());
+    tokio::task::spawn_blocking(move || shutdown.wait_idle()).await?;
+    Ok(())
+}
+
+pub async fn handle_server(mut stream: TcpStream, config: &ServerConfig, session: &mut SessionGuard) {
+    if let Err(e) = stream.set_nodelay(true) {
+        warn!("Failed to set nodelay: {}", e);
+    }
+
+    let context = logger::current_context()
+        .unwrap_or_else(|| LogContext::new(stream.peer_addr().ok()));
+    let mut observer = Observer::new(config, &context);
+
+    let mut deadline = config.timeout(&BTimeout::Normal);
+    let mut state = BState::initial();
+    let mut decoder = FrameDecoder::new();
+
+    loop {
+        match session.verdict(&state) {
+            Verdict::Continue => {}
+            Verdict::Logout => {
+                info!("Shutting down, logging the robot out");
+                session.abort();
+                observer.shutdown();
+                if let Err(e) = server_send_message(&mut stream, &mut observer, ServerMessage::Logout, deadline).await {
+                    error!("Error: {}", e);
+                }
+                server_shutdown(&mut stream).await;
+                return;
+            }
+            Verdict::Close => {
+                info!("Shutting down, closing the session");
+                session.abort();
+                observer.shutdown();
+                server_shutdown(&mut stream).await;
+                return;
+            }
+        }
+
+        let max_len = state.expected_mess_lenth();
+        let login_key = state.login_key();
+        let res = match read_message(&mut stream, &mut decoder, max_len, deadline).await {
+            Ok(mess) if lockout::reject_username(&state, &mess) => {
+                observer.client(&mess);
+                observer.locked_out();
+                server_shutdown(&mut stream).await;
+                return;
+            }
+            Ok(mess) => {
+                observer.client(&mess);
+                state.handle_message(mess, config)
+                    .inspect_err(|e| observer.failed(e, false, login_key))
+            }
+            Err(e) => {
+                observer.failed(&e, true, login_key);
+                Err(e)
+            }
+        };
+
+        let (new_state, action) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                server_send_error(&mut stream, &mut observer, e, deadline).await;
+                return;
+            }
+        };
+        state = new_state;
+
+        let sent = match action {
+            PRes::SendMessage(message) =>
+                server_send_message(&mut stream, &mut observer, message, deadline).await,
+
+            PRes::SendMessages(messages) => {
+                let mut sent = Ok(());
+                for message in messages {
+                    sent = server_send_message(&mut stream, &mut observer, message, deadline).await;
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+                sent
+            },
+
+            PRes::UpdateTimeout(timeout) => {
+                observer.timeout(&timeout);
+                deadline = config.timeout(&timeout);
+                Ok(())
+            },
+
+            PRes::Finish(messages, response, stats) => {
+                for message in &messages {
+                    info!("The message was \"{}\"", message);
+                }
+                info!("Path: {}", stats);
+                observer.finished(&stats);
+                if let Err(e) = server_send_message(&mut stream, &mut observer, response, deadline).await {
+                    error!("Error: {}", e);
+                }
+                server_shutdown(&mut stream).await;
+                return;
+            }
+        };
+
+        if let Err(e) = sent {
+            error!("Error: {}", e);
+            observer.failed(&e, true, None);
+            server_shutdown(&mut stream).await;
+            return;
+        }
+    }
+}
+
+async fn read_message(
+    stream: &mut TcpStream, decoder: &mut FrameDecoder, max_len: usize, deadline: Duration,
+) -> Result<ClientMessage, BError> {
+    let mut bytes = [0; 256];
+
+    loop {
+        if let Some(message) = decoder.next_frame(max_len)? {
+            debug!("> Read: {}", message.0);
+            return Ok(message);
+        }
+
+        let bytes_num = timeout(deadline, stream.read(&mut bytes)).await
+            .map_err(|_| BError::Io(deadline_elapsed()))?
+            .map_err(BError::Io)?;
+        if bytes_num == 0 {
+            return Err(BError::ConnectionClosed);
+        }
+        decoder.push(&bytes[..bytes_num]);
+    }
+}
+
+async fn server_send_message(
+    stream: &mut TcpStream, observer: &mut Observer<'_>, message: ServerMessage, deadline: Duration,
+) -> Result<(), BError> {
+    observer.server(&message);
+    let payload = message.to_payload();
+    debug!("# Send: {}", String::from_utf8_lossy(&payload));
+
+    timeout(deadline, stream.write_all(&payload)).await
+        .map_err(|_| BError::WriteFailed(deadline_elapsed()))?
+        .map_err(BError::WriteFailed)
+}
+
+async fn server_send_error(stream: &mut TcpStream, observer: &mut Observer<'_>, error: BError, deadline: Duration) {
+    error!("Error: {}", error);
+
+    if error.should_send() {
+        if let Err(e) = server_send_message(stream, observer, error.server_response(), deadline).await {
+            error!("Error: {}", e);
+        }
+    }
+
+    server_shutdown(stream).await;
+}
+
+async fn server_shutdown(stream: &mut TcpStream) {
+    debug!("Stopping a stream");
+    if let Err(e) = stream.shutdown().await {
+        warn!("Server didn't shudown as expected: {}", e);
+    }
+}
+
+fn deadline_elapsed() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "read/write deadline elapsed")
+}
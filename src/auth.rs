@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::config::{key_table, load_key_table, parse_key_pair, ConfigError, ServerConfig};
+use crate::constants::{ServerSecret, KEY_FILE_POLL};
+use crate::errors::BError;
+use crate::hmac_auth::AuthMode;
+use crate::state_machine::login_hash;
+
+/// Decides who may log in and which confirmations are exchanged during the login
+pub trait Authenticator: Send + Sync {
+    /// Checked with the username before the key index is requested
+    fn accepts(&self, username: &str) -> bool {
+        let _ = username;
+        true
+    }
+
+    /// The server and the client confirmation for the username and key index,
+    /// `None` if the robot has no such key
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)>;
+
+    /// The secret of the key index in the hmac mode, `None` if the robot has no such key
+    fn hmac_secret(&self, username: &str, index: usize) -> Option<String> {
+        let _ = (username, index);
+        None
+    }
+
+    /// Checks the confirmation sent by the robot against the expected one
+    fn validate(&self, username: &str, expected: u32, actual: u32) -> Result<(), BError> {
+        let _ = username;
+        if expected != actual {
+            return Err(BError::HashMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Reloads the keys from their file if it changed since the last load (always with `force`),
+    /// returns whether anything was reloaded
+    fn reload(&self, force: bool) -> Result<bool, ConfigError> {
+        let _ = force;
+        Ok(false)
+    }
+}
+
+/// The assignment's scheme, the username byte sum combined with a key from one shared table
+pub struct ByteSum {
+    secrets: Vec<ServerSecret>,
+}
+
+impl ByteSum {
+
+    pub fn new(secrets: Vec<ServerSecret>) -> ByteSum {
+        ByteSum { secrets }
+    }
+}
+
+impl Authenticator for ByteSum {
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)> {
+        self.secrets.get(index).map(|secret| login_hash(username, secret))
+    }
+}
+
+/// Secrets shared by all the robots in the hmac mode, there are no classic confirmations
+pub struct HmacKeys {
+    secrets: Vec<String>,
+}
+
+impl HmacKeys {
+
+    pub fn new(secrets: Vec<String>) -> HmacKeys {
+        HmacKeys { secrets }
+    }
+}
+
+impl Authenticator for HmacKeys {
+    fn confirmations(&self, _username: &str, _index: usize) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn hmac_secret(&self, _username: &str, index: usize) -> Option<String> {
+        self.secrets.get(index).cloned()
+    }
+}
+
+/// The byte-sum scheme with the key table read from a file that can be replaced while running.
+///
+/// Sessions waiting for the confirmation already know the hash they expect,
+/// so they finish the login with the key they started with.
+pub struct KeyFile {
+    path: String,
+    secrets: RwLock<Arc<Vec<ServerSecret>>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl KeyFile {
+
+    /// `secrets` is the table already loaded from the file
+    pub fn new(path: &str, secrets: Vec<ServerSecret>) -> KeyFile {
+        KeyFile {
+            path: path.to_string(),
+            secrets: RwLock::new(Arc::new(secrets)),
+            modified: Mutex::new(modified(path)),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<KeyFile, ConfigError> {
+        Ok(KeyFile::new(path, load_key_table(path)?))
+    }
+
+    pub fn secrets(&self) -> Arc<Vec<ServerSecret>> {
+        Arc::clone(&self.secrets.read().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl Authenticator for KeyFile {
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)> {
+        self.secrets().get(index).map(|secret| login_hash(username, secret))
+    }
+
+    fn reload(&self, force: bool) -> Result<bool, ConfigError> {
+        let mut last = self.modified.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let current = modified(&self.path);
+        if !force && current == *last {
+            return Ok(false);
+        }
+        // A broken file is reported once, not on every check
+        *last = current;
+
+        let secrets = load_key_table(&self.path)?;
+        *self.secrets.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(secrets);
+        Ok(true)
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Every robot has its own key table, robots missing from it cannot log in
+pub struct RobotKeys {
+    keys: HashMap<String, Vec<ServerSecret>>,
+}
+
+impl RobotKeys {
+
+    pub fn new(keys: HashMap<String, Vec<ServerSecret>>) -> RobotKeys {
+        RobotKeys { keys }
+    }
+
+    /// Reads a file with a `[username]` section per robot holding `ID = SERVER CLIENT` lines
+    pub fn load(path: &str) -> Result<RobotKeys, ConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+        let mut entries: Vec<(String, Vec<(usize, ServerSecret)>)> = Vec::new();
+        for (index, raw) in content.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let syntax = || ConfigError::Syntax { line: index + 1, content: raw.to_string() };
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let username = &line[1..line.len() - 1];
+                if username.is_empty() || entries.iter().any(|(name, _)| name == username) {
+                    return Err(syntax());
+                }
+                entries.push((username.to_string(), Vec::new()));
+                continue;
+            }
+
+            let (id, pair) = line.split_once('=').ok_or_else(syntax)?;
+            let (_, keys) = entries.last_mut().ok_or_else(syntax)?;
+            keys.push(parse_key_pair(id.trim(), pair.trim())?);
+        }
+
+        let mut keys = HashMap::new();
+        for (username, entries) in entries {
+            let table = match key_table(entries) {
+                Err(ConfigError::InvalidKeyTable(reason)) =>
+                    return Err(ConfigError::InvalidKeyTable(format!("{} of {}", reason, username))),
+                table => table?,
+            };
+            keys.insert(username, table);
+        }
+        Ok(RobotKeys::new(keys))
+    }
+}
+
+impl Authenticator for RobotKeys {
+    fn accepts(&self, username: &str) -> bool {
+        self.keys.contains_key(username)
+    }
+
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)> {
+        let secret = self.keys.get(username)?.get(index)?;
+        Some(login_hash(username, secret))
+    }
+}
+
+/// Lets only the listed usernames through to another authenticator
+pub struct AllowList {
+    usernames: HashSet<String>,
+    inner: Box<dyn Authenticator>,
+}
+
+impl AllowList {
+
+    pub fn new(usernames: HashSet<String>, inner: Box<dyn Authenticator>) -> AllowList {
+        AllowList { usernames, inner }
+    }
+
+    /// Reads a file with one username per line
+    pub fn load(path: &str, inner: Box<dyn Authenticator>) -> Result<AllowList, ConfigError> {
+        Ok(AllowList::new(read_usernames(path)?, inner))
+    }
+}
+
+fn read_usernames(path: &str) -> Result<HashSet<String>, ConfigError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+impl Authenticator for AllowList {
+    fn accepts(&self, username: &str) -> bool {
+        self.usernames.contains(username) && self.inner.accepts(username)
+    }
+
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)> {
+        self.inner.confirmations(username, index)
+    }
+
+    fn hmac_secret(&self, username: &str, index: usize) -> Option<String> {
+        self.inner.hmac_secret(username, index)
+    }
+
+    fn validate(&self, username: &str, expected: u32, actual: u32) -> Result<(), BError> {
+        self.inner.validate(username, expected, actual)
+    }
+
+    fn reload(&self, force: bool) -> Result<bool, ConfigError> {
+        self.inner.reload(force)
+    }
+}
+
+/// The authenticator described by the config, the key files are read right away
+pub fn build(config: &ServerConfig) -> Result<Box<dyn Authenticator>, ConfigError> {
+    let mut auth: Box<dyn Authenticator> = match (config.auth_mode, &config.robot_keys, &config.key_file) {
+        (AuthMode::Hmac, _, _) => Box::new(HmacKeys::new(config.hmac_keys.clone())),
+        (_, Some(path), _) => Box::new(RobotKeys::load(path)?),
+        (_, None, Some(path)) => Box::new(KeyFile::load(path)?),
+        (_, None, None) => Box::new(ByteSum::new(config.secrets.clone())),
+    };
+    if let Some(path) = &config.allow_list {
+        auth = Box::new(AllowList::load(path, auth)?);
+    }
+    Ok(auth)
+}
+
+/// Checks the key file for changes every `KEY_FILE_POLL` and right away when `hangup` is set (by SIGHUP).
+/// Runs on its own thread for as long as the server does.
+pub fn watch(auth: Arc<dyn Authenticator>, hangup: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        let force = hangup.swap(false, Ordering::SeqCst);
+        match auth.reload(force) {
+            Ok(true) => info!("Key table reloaded"),
+            Ok(false) if force => info!("Nothing to reload"),
+            Ok(false) => {}
+            Err(e) => error!("Keeping the old keys, {}", e),
+        }
+        thread::sleep(KEY_FILE_POLL);
+    });
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::errors::BError;
+use crate::messages::ServerMessage;
+use crate::navigation::{NavigationStrategy, Step};
+use crate::path::Orient;
+
+/// Goes around the cell in front of the robot and faces the same way again, left side first
+const DETOUR: [ServerMessage; 8] = [
+    ServerMessage::Left, ServerMessage::Move, ServerMessage::Right,
+    ServerMessage::Move, ServerMessage::Move, ServerMessage::Right,
+    ServerMessage::Move, ServerMessage::Left,
+];
+
+#[derive(Debug)]
+enum Phase {
+    Start,
+    FindPath,
+    FollowAxis,
+}
+
+/// Moves greedily towards an axis through the target, then follows the axis to the target.
+///
+/// Obstacles in the open are avoided by turning, obstacles on the axis
+/// by a fixed detour around a single cell. Obstacles known from the shared map
+/// are avoided the same way without bumping into them first.
+#[derive(Debug)]
+pub struct AxisStrategy {
+    phase: Phase,
+    position: (i32, i32),
+    orient: Orient,
+    target: (i32, i32),
+    /// Commands sent without looking at the reported position
+    queue: VecDeque<ServerMessage>,
+    /// Obstacles found on the map by other sessions
+    known: HashSet<(i32, i32)>,
+    /// Obstacles found since the last `discovered` call
+    discovered: Vec<(i32, i32)>,
+}
+
+impl AxisStrategy {
+
+    pub fn new(position: (i32, i32), orient: Orient, target: (i32, i32), known: HashSet<(i32, i32)>) -> AxisStrategy {
+        AxisStrategy {
+            phase: Phase::Start,
+            position,
+            orient,
+            target,
+            queue: VecDeque::new(),
+            known,
+            discovered: Vec::new(),
+        }
+    }
+
+    /// Whether the last move from `position` was blocked, remembers the obstacle if so.
+    /// With an empty queue the last command sent was always a move.
+    fn blocked(&mut self, position: (i32, i32)) -> bool {
+        if position != self.position {
+            return false;
+        }
+        let obstacle = self.orient.move_in(position);
+        if self.known.insert(obstacle) {
+            debug!("+ Axis: Obstacle found at {:?}", obstacle);
+            self.discovered.push(obstacle);
+        }
+        true
+    }
+
+    /// Whether the next move from `position` runs into a known obstacle
+    fn known_ahead(&self, position: (i32, i32)) -> bool {
+        self.is_known(self.orient.move_in(position))
+    }
+
+    /// The target is never avoided, even if a stale map says it is blocked
+    fn is_known(&self, cell: (i32, i32)) -> bool {
+        cell != self.target && self.known.contains(&cell)
+    }
+
+    /// Turns the shortest way to face along the axis towards the target and moves
+    fn follow_axis(&mut self, (x, y): (i32, i32)) -> Step {
+        let (tx, ty) = self.target;
+        let along = if x == tx {
+            if y > ty { Orient::SOUTH } else { Orient::NORTH }
+        } else if x > tx {
+            Orient::WEST
+        } else {
+            Orient::EAST
+        };
+        let ahead = along.move_in((x, y));
+        if self.is_known(ahead) {
+            debug!("+ Axis: Known obstacle on the axis, going around");
+            // Turning to the axis and the first turn of the detour in one go
+            let mut commands = self.orient.rotate_to(along.left());
+            commands.extend(DETOUR[1..].iter().cloned());
+            return self.send(Phase::FollowAxis, ahead, along, &commands);
+        }
+
+        let mut commands = self.orient.rotate_to(along);
+        commands.push(ServerMessage::Move);
+        self.send(Phase::FollowAxis, (x, y), along, &commands)
+    }
+
+    /// Turns the shortest way to a direction closer to the target and moves
+    fn head_for_axis(&mut self, position: (i32, i32)) -> Step {
+        let towards = Orient::ALL.into_iter()
+            .filter(|orient| orient.is_valid_for(position, self.target))
+            .min_by_key(|orient| self.orient.rotate_to(*orient).len())
+            .expect("Some direction leads closer to a different cell");
+        let mut commands = self.orient.rotate_to(towards);
+        commands.push(ServerMessage::Move);
+        self.send(Phase::FindPath, position, towards, &commands)
+    }
+
+    fn send(&mut self, phase: Phase, position: (i32, i32), orient: Orient, commands: &[ServerMessage]) -> Step {
+        self.phase = phase;
+        self.position = position;
+        self.orient = orient;
+        self.queue.extend(commands[1..].iter().cloned());
+        Step::Send(commands[0].clone())
+    }
+}
+
+impl NavigationStrategy for AxisStrategy {
+
+    fn next(&mut self, (x, y): (i32, i32)) -> Result<Step, BError> {
+        if let Some(command) = self.queue.pop_front() {
+            return Ok(Step::Send(command));
+        }
+
+        let (tx, ty) = self.target;
+        let orient = self.orient;
+
+        let step = match self.phase {
+            Phase::Start => {
+                if x == tx && y == ty {
+                    debug!("+ Axis: Ready to extract");
+                    Step::Arrived
+                } else if x == tx || y == ty {
+                    debug!("+ Axis: Ready to follow axis");
+                    self.follow_axis((x, y))
+                } else if orient.is_valid_for((x, y), self.target) {
+                    debug!("+ Axis: Orientation is valid");
+                    self.send(Phase::FindPath, (x, y), orient, &[ServerMessage::Move])
+                } else {
+                    debug!("+ Axis: Rotating by π");
+                    let back = orient.left().left();
+                    let mut commands = orient.rotate_to(back);
+                    commands.push(ServerMessage::Move);
+                    self.send(Phase::FindPath, (x, y), back, &commands)
+                }
+            }
+
+            Phase::FindPath => {
+                if x == tx || y == ty {
+                    debug!("+ Axis: Moving to axis");
+                    self.follow_axis((x, y))
+                } else if self.blocked((x, y)) || self.known_ahead((x, y)) {
+                    if orient.left().is_valid_for((x, y), self.target) {
+                        debug!("+ Axis: Obsticle hit! Rotating left");
+                        let commands = [ServerMessage::Left, ServerMessage::Move];
+                        self.send(Phase::FindPath, (x, y), orient.left(), &commands)
+                    } else {
+                        debug!("+ Axis: Obsticle hit! Rotating right");
+                        let commands = [ServerMessage::Right, ServerMessage::Move];
+                        self.send(Phase::FindPath, (x, y), orient.right(), &commands)
+                    }
+                } else {
+                    debug!("+ Axis: No problemo, let's move again");
+                    self.send(Phase::FindPath, (x, y), orient, &[ServerMessage::Move])
+                }
+            }
+
+            Phase::FollowAxis => {
+                if x == tx && y == ty {
+                    Step::Arrived
+                } else if self.blocked((x, y)) || self.known_ahead((x, y)) {
+                    debug!("+ Axis: Obsticle hit, going around");
+                    self.send(Phase::FollowAxis, orient.move_in((x, y)), orient, &DETOUR)
+                } else if x == tx || y == ty {
+                    debug!("+ Axis: No problemo, lets move");
+                    self.follow_axis((x, y))
+                } else {
+                    // A move of the detour was blocked
+                    debug!("+ Axis: Off the axis, heading back");
+                    self.head_for_axis((x, y))
+                }
+            }
+        };
+        Ok(step)
+    }
+
+    fn orient(&self) -> Orient {
+        self.orient
+    }
+
+    fn discovered(&mut self) -> Vec<(i32, i32)> {
+        std::mem::take(&mut self.discovered)
+    }
+}
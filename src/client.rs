@@ -0,0 +1,243 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::auth::{Authenticator, ByteSum, HmacKeys};
+use crate::codec::FrameDecoder;
+use crate::constants::ServerSecret;
+use crate::errors::BError;
+use crate::hmac_auth::{self, AuthMode};
+use crate::messages::{ClientMessage, ServerMessage};
+
+/// The longest server message including the terminator
+const SERVER_MAX_LEN: usize = 100;
+
+/// Why the robot side of a session failed
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// The server sent bytes that are not a valid message
+    Frame(BError),
+    /// The server answered with an error message
+    Rejected(ServerMessage),
+    /// The server confirmation does not match the key
+    ServerConfirmation { expected: u32, actual: String },
+    /// The key index is not in the robot's key table
+    UnknownKey(usize),
+    /// The server sent something else than the protocol allows at this point
+    Unexpected(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Frame(e) => write!(f, "Invalid message from the server: {}", e),
+            Self::Rejected(message) => write!(f, "Server answered \"{}\"", message.body()),
+            Self::ServerConfirmation { expected, actual } =>
+                write!(f, "Server confirmation \"{}\" does not match, expected {}", actual, expected),
+            Self::UnknownKey(key) => write!(f, "Key index {} is not in the key table", key),
+            Self::Unexpected(body) => write!(f, "Unexpected message \"{}\"", body),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// The robot side of the protocol, using the same framing as the server.
+///
+/// After `login` the server commands are read one by one with `next_command` until `Logout`,
+/// the robot answers every move and turn with `report` and the pick up with `send_message`.
+pub struct RobotClient {
+    stream: TcpStream,
+    decoder: FrameDecoder,
+    /// The robot's keys, looked up the same way the server does
+    keys: Arc<dyn Authenticator>,
+    mode: AuthMode,
+    /// Payload of a held back message, sent with the next one
+    held: Vec<u8>,
+    holding: bool,
+    /// Chunk size and delay when every message is written in pieces
+    segments: Option<(usize, Duration)>,
+}
+
+impl RobotClient {
+
+    /// Uses the assignment's key table, see `with_secrets`, `with_hmac_keys` and `with_authenticator`
+    pub fn new(stream: TcpStream) -> RobotClient {
+        RobotClient {
+            stream,
+            decoder: FrameDecoder::new(),
+            keys: Arc::new(ByteSum::new(ServerSecret::secrets())),
+            mode: AuthMode::Classic,
+            held: Vec::new(),
+            holding: false,
+            segments: None,
+        }
+    }
+
+    pub fn connect(addr: &str, timeout: Duration) -> io::Result<RobotClient> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(RobotClient::new(stream))
+    }
+
+    /// The key table of the classic login
+    pub fn with_secrets(self, secrets: Vec<ServerSecret>) -> RobotClient {
+        self.with_authenticator(Arc::new(ByteSum::new(secrets)), AuthMode::Classic)
+    }
+
+    /// Logs in with the HMAC answers, `secrets` are indexed by the key index
+    pub fn with_hmac_keys(self, secrets: Vec<String>) -> RobotClient {
+        self.with_authenticator(Arc::new(HmacKeys::new(secrets)), AuthMode::Hmac)
+    }
+
+    /// Takes the confirmations (or the HMAC secrets) from the authenticator,
+    /// e.g. the server's own to log in with per robot keys or a key file
+    pub fn with_authenticator(mut self, keys: Arc<dyn Authenticator>, mode: AuthMode) -> RobotClient {
+        self.keys = keys;
+        self.mode = mode;
+        self
+    }
+
+    /// Writes every message in chunks of `size` bytes with `delay` between them
+    pub fn with_segments(mut self, size: usize, delay: Duration) -> RobotClient {
+        self.segments = Some((size.max(1), delay));
+        self
+    }
+
+    /// Sends the username and the key index, checks the server confirmation
+    /// and answers it, succeeds once the server accepts the login
+    pub fn login(&mut self, username: &str, key: usize) -> Result<(), ClientError> {
+        self.send(username)?;
+        self.expect(ServerMessage::KeyRequest)?;
+
+        self.send(&key.to_string())?;
+        let reply = self.receive()?;
+        if let Some(message) = ServerMessage::parse(&reply).filter(is_error) {
+            return Err(ClientError::Rejected(message));
+        }
+        let answer = match self.mode {
+            AuthMode::Classic => {
+                let (server, client) = self.keys.confirmations(username, key).ok_or(ClientError::UnknownKey(key))?;
+                if reply != server.to_string() {
+                    return Err(ClientError::ServerConfirmation { expected: server, actual: reply });
+                }
+                client.to_string()
+            }
+            AuthMode::Hmac => {
+                let secret = self.keys.hmac_secret(username, key).ok_or(ClientError::UnknownKey(key))?;
+                hmac_auth::answer(&secret, username, &reply)
+            }
+        };
+
+        self.send(&answer)?;
+        self.expect(ServerMessage::NoProblemo)
+    }
+
+    /// The next command, one of `Move`, `Left`, `Right`, `PickUp` and `Logout`,
+    /// error messages from the server are `Rejected`
+    pub fn next_command(&mut self) -> Result<ServerMessage, ClientError> {
+        let reply = self.receive()?;
+        match ServerMessage::parse(&reply) {
+            Some(command @ (ServerMessage::Move
+                | ServerMessage::Left
+                | ServerMessage::Right
+                | ServerMessage::PickUp
+                | ServerMessage::Logout)) => Ok(command),
+            Some(message) if is_error(&message) => Err(ClientError::Rejected(message)),
+            _ => Err(ClientError::Unexpected(reply)),
+        }
+    }
+
+    /// Answers a move or a turn with the position, `OK x y`
+    pub fn report(&mut self, position: (i32, i32)) -> Result<(), ClientError> {
+        self.send(&format!("OK {} {}", position.0, position.1))
+    }
+
+    /// Answers `PickUp` with the secret message, empty if there is none
+    pub fn send_message(&mut self, message: &str) -> Result<(), ClientError> {
+        self.send(message)
+    }
+
+    pub fn recharging(&mut self) -> Result<(), ClientError> {
+        self.send("RECHARGING")
+    }
+
+    pub fn full_power(&mut self) -> Result<(), ClientError> {
+        self.send("FULL POWER")
+    }
+
+    /// Holds the next message back and sends it in one packet with the one after it
+    pub fn hold(&mut self) {
+        self.holding = true;
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), ClientError> {
+        debug!("# Send: {}", message);
+        self.held.extend_from_slice(&ClientMessage(message.to_string()).to_payload());
+        if self.holding {
+            self.holding = false;
+            return Ok(());
+        }
+
+        let payload = std::mem::take(&mut self.held);
+        match self.segments {
+            None => self.stream.write_all(&payload)?,
+            Some((size, delay)) => for chunk in payload.chunks(size) {
+                self.stream.write_all(chunk)?;
+                thread::sleep(delay);
+            },
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<String, ClientError> {
+        let mut bytes = [0; 256];
+        loop {
+            if let Some(message) = self.decoder.next_frame(SERVER_MAX_LEN).map_err(ClientError::Frame)? {
+                debug!("> Read: {}", message.0);
+                return Ok(message.0);
+            }
+
+            let bytes_num = self.stream.read(&mut bytes)?;
+            if bytes_num == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.decoder.push(&bytes[..bytes_num]);
+        }
+    }
+
+    fn expect(&mut self, expected: ServerMessage) -> Result<(), ClientError> {
+        let reply = self.receive()?;
+        if reply == expected.body() {
+            return Ok(());
+        }
+        Err(rejected(&reply, ClientError::Unexpected(reply.clone())))
+    }
+}
+
+fn is_error(message: &ServerMessage) -> bool {
+    matches!(message,
+        ServerMessage::LoginFailed
+        | ServerMessage::SyntaxError
+        | ServerMessage::LogicError
+        | ServerMessage::KeyOutOfRangeError)
+}
+
+/// `Rejected` if the reply is an error message, `otherwise` if not
+fn rejected(reply: &str, otherwise: ClientError) -> ClientError {
+    match ServerMessage::parse(reply).filter(is_error) {
+        Some(message) => ClientError::Rejected(message),
+        None => otherwise,
+    }
+}
@@ -0,0 +1,89 @@
+use crate::errors::BError;
+use crate::messages::ClientMessage;
+
+const TERMINATOR: [u8; 2] = [7u8, 8u8]; // \a\b
+
+/// `RECHARGING` and `FULL POWER` can arrive in any state,
+/// so their limit applies even if the state expects shorter messages.
+const CHARGING_MESSAGES: [&[u8]; 2] = [b"RECHARGING", b"FULL POWER"];
+const CHARGING_MAX_LEN: usize = 12;
+
+/// Splits the incoming byte stream into `\a\b` terminated messages.
+///
+/// Bytes are pushed in chunks of any size, whatever is left after a complete
+/// message is kept for the next one, so segmented and merged packets both work.
+/// Limits include the terminator.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    scanned: usize,
+}
+
+impl FrameDecoder {
+
+    pub fn new() -> FrameDecoder {
+        FrameDecoder::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Returns the next complete message, `None` if more bytes are needed
+    /// or an error as soon as the pending message cannot fit in `max_len`.
+    pub fn next_frame(&mut self, max_len: usize) -> Result<Option<ClientMessage>, BError> {
+        match self.find_terminator() {
+            Some(end) => {
+                let frame = &self.buffer[..end];
+                if is_overflow(max_len, frame, end + TERMINATOR.len()) {
+                    return Err(self.too_long(end));
+                }
+
+                let bytes = frame.to_vec();
+                self.buffer.drain(..end + TERMINATOR.len());
+                self.scanned = 0;
+                let str = String::from_utf8(bytes)
+                    .map_err(|e| BError::InvalidUtf8(e.into_bytes()))?;
+                Ok(Some(ClientMessage(str)))
+            }
+            None => {
+                let len = self.buffer.len();
+                let pending = if self.buffer.last() == Some(&TERMINATOR[0]) { len + 1 } else { len + 2 };
+                if is_overflow(max_len, &self.buffer, pending) {
+                    return Err(self.too_long(len));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn find_terminator(&mut self) -> Option<usize> {
+        let found = self.buffer[self.scanned..]
+            .windows(TERMINATOR.len())
+            .position(|window| window == TERMINATOR)
+            .map(|pos| self.scanned + pos);
+
+        if found.is_none() {
+            self.scanned = self.buffer.len().saturating_sub(TERMINATOR.len() - 1);
+        }
+        found
+    }
+
+    fn too_long(&self, len: usize) -> BError {
+        let message = String::from_utf8_lossy(&self.buffer[..len]).into_owned();
+        BError::MessageToLong(message, len)
+    }
+}
+
+/// Checks if a message with the `frame` content (possibly incomplete)
+/// needing at least `total` bytes including the terminator is over the limit.
+fn is_overflow(max_len: usize, frame: &[u8], total: usize) -> bool {
+    let is_charging = CHARGING_MESSAGES.iter()
+        .any(|word| word.starts_with(frame) || frame.starts_with(word));
+
+    total > max_len && !(is_charging && total <= CHARGING_MAX_LEN)
+}
@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::{self, Authenticator};
+use crate::hmac_auth::AuthMode;
+
+use crate::constants::{BTimeout, ServerSecret, DEFAULT_GRACE_PERIOD, DEFAULT_HOST, DEFAULT_LOCKOUT, DEFAULT_LOCKOUT_AFTER, DEFAULT_MAX_SESSIONS, DEFAULT_PORT, DEFAULT_STEP_BUDGET};
+use crate::logger::{LogFormat, LogLevel};
+use crate::mission::Mission;
+use crate::navigation::Strategy;
+
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub timeout_normal: Duration,
+    pub timeout_refilling: Duration,
+    pub secrets: Vec<ServerSecret>,
+    pub auth_mode: AuthMode,
+    /// Secrets of the key indexes in the HMAC mode
+    pub hmac_keys: Vec<String>,
+    /// File the key table is loaded from, replacing `secrets` and reloaded when it changes
+    pub key_file: Option<String>,
+    /// File with a key table per robot, replacing `secrets`
+    pub robot_keys: Option<String>,
+    /// File listing the usernames allowed to log in
+    pub allow_list: Option<String>,
+    /// Built from the key settings above by `from_args`,
+    /// `build_authenticator` rebuilds it after they are changed
+    pub authenticator: Arc<dyn Authenticator>,
+    /// Failed logins in a row before the IP address or username is locked out, 0 disables it
+    pub lockout_after: u32,
+    /// First lockout, doubled by every further failure
+    pub lockout: Duration,
+    /// New connections accepted per second, 0 for no limit
+    pub connection_rate: u32,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    pub runtime: Runtime,
+    pub max_sessions: usize,
+    pub grace_period: Duration,
+    pub strategy: Strategy,
+    /// Commands a session may send while navigating before the target is given up
+    pub step_budget: u32,
+    /// Where the robots pick up the message
+    pub target: (i32, i32),
+    /// Targets of specific usernames, taking precedence over `target`
+    pub user_targets: HashMap<String, (i32, i32)>,
+    /// Waypoints to pick up messages at, replacing `target`
+    pub mission: Option<Mission>,
+    /// Missions of specific usernames, taking precedence over everything else
+    pub user_missions: HashMap<String, Mission>,
+    /// Map ID of the obstacles shared by the sessions, `None` keeps every session's world separate
+    pub map: Option<String>,
+    /// File the shared obstacles are loaded from and saved to
+    pub map_file: Option<String>,
+    /// Directory to write session transcripts to
+    pub record_dir: Option<String>,
+    /// Address of the HTTP listener serving Prometheus metrics
+    pub metrics_addr: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Runtime {
+    /// One OS thread per connection
+    Threads,
+    /// Tokio tasks, requires the `async` feature
+    Async,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Help,
+    Io(String, std::io::Error),
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue { key: String, value: String },
+    Syntax { line: usize, content: String },
+    InvalidKeyTable(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Help => write!(f, "{}", USAGE),
+            Self::Io(path, e) => write!(f, "cannot read {}: {}", path, e),
+            Self::UnknownFlag(flag) => write!(f, "unknown option {}\n\n{}", flag, USAGE),
+            Self::MissingValue(flag) => write!(f, "missing value for {}", flag),
+            Self::InvalidValue { key, value } => write!(f, "invalid value \"{}\" for {}", value, key),
+            Self::Syntax { line, content } => write!(f, "config syntax error on line {}: \"{}\"", line, content),
+            Self::InvalidKeyTable(reason) => write!(f, "invalid key table: {}", reason),
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Usage: bobika [OPTIONS]
+       bobika replay <TRANSCRIPT> [OPTIONS]
+       bobika visualize <TRANSCRIPT> [OPTIONS]
+       bobika simulate [SCENARIO...] [OPTIONS]
+
+Options:
+  -c, --config <FILE>          Load settings from an INI-style config file
+  -H, --host <HOST>            Address to bind to
+  -p, --port <PORT>            Port to listen on
+      --timeout <SECS>         Read/write timeout in normal mode
+      --refill-timeout <SECS>  Read/write timeout while the robot is recharging
+      --key <ID>=<S>,<C>       Key pair for the key index ID (repeatable, replaces the table)
+      --auth-mode <MODE>       classic (byte sum confirmations) or hmac (nonce and HMAC answer)
+      --hmac-key <ID>=<SECRET> Secret of the key index ID in the hmac mode (repeatable)
+      --key-file <FILE>        Load the key table from FILE (ID = S C lines), reloaded
+                               on SIGHUP or when the file changes
+      --robot-keys <FILE>      Key tables per robot, only the robots in FILE can log in
+      --allow-list <FILE>      Usernames allowed to log in, one per line
+      --lockout-after <N>      Failed logins before the IP or username is locked out (0 = never)
+      --lockout <SECS>         First lockout, doubled by every further failure
+      --connection-rate <N>    New connections accepted per second (0 = no limit)
+      --log-level <LEVEL>      One of error, warn, info, debug
+      --log-format <FORMAT>    text or json (one object per line)
+      --runtime <RUNTIME>      threads or async (needs the async feature)
+      --max-sessions <N>       Concurrent sessions limit in the async runtime
+      --grace-period <SECS>    Time given to running sessions on SIGINT/SIGTERM
+      --target <X>,<Y>         Where the robots pick up the message
+      --user-target <NAME>=<X>,<Y>
+                               Target for the robot logging in as NAME (repeatable)
+      --mission <X>,<Y>;<X>,<Y>...
+                               Waypoints to pick up messages at, in order
+      --user-mission <NAME>=<X>,<Y>;<X>,<Y>...
+                               Mission for the robot logging in as NAME (repeatable)
+      --strategy <STRATEGY>    Navigation, planner (shortest path) or axis (greedy)
+      --step-budget <N>        Navigation commands per session before giving up
+      --map <ID>               Share found obstacles between sessions on map ID (off to disable)
+      --map-file <FILE>        Load the shared obstacles from FILE, save them there
+                               every few seconds when they change and on exit
+      --record <DIR>           Write a transcript of every session into DIR
+      --metrics <ADDR>         Serve Prometheus metrics on http://ADDR/metrics
+  -h, --help                   Print this help
+
+Command line flags override values from the config file.";
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            timeout_normal: BTimeout::Normal.default_value(),
+            timeout_refilling: BTimeout::Refilling.default_value(),
+            secrets: ServerSecret::secrets(),
+            auth_mode: AuthMode::Classic,
+            hmac_keys: Vec::new(),
+            key_file: None,
+            robot_keys: None,
+            allow_list: None,
+            authenticator: Arc::new(auth::ByteSum::new(ServerSecret::secrets())),
+            lockout_after: DEFAULT_LOCKOUT_AFTER,
+            lockout: DEFAULT_LOCKOUT,
+            connection_rate: 0,
+            log_level: LogLevel::Debug,
+            log_format: LogFormat::Text,
+            runtime: Runtime::Threads,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            strategy: Strategy::Planner,
+            step_budget: DEFAULT_STEP_BUDGET,
+            target: (0, 0),
+            user_targets: HashMap::new(),
+            mission: None,
+            user_missions: HashMap::new(),
+            map: None,
+            map_file: None,
+            record_dir: None,
+            metrics_addr: None,
+        }
+    }
+}
+
+impl ServerConfig {
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn timeout(&self, timeout: &BTimeout) -> Duration {
+        match timeout {
+            BTimeout::Normal => self.timeout_normal,
+            BTimeout::Refilling => self.timeout_refilling,
+        }
+    }
+
+    /// The waypoints of the robot logging in with the username
+    pub fn mission_for(&self, username: &str) -> Mission {
+        if let Some(mission) = self.user_missions.get(username) {
+            return mission.clone();
+        }
+        if let Some(target) = self.user_targets.get(username) {
+            return Mission::single(*target);
+        }
+        match &self.mission {
+            Some(mission) => mission.clone(),
+            None => Mission::single(self.target),
+        }
+    }
+
+    /// Builds the config from the defaults, the optional config file
+    /// and the command line flags (in this order of precedence).
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<ServerConfig, ConfigError> {
+        let flags = parse_flags(args)?;
+
+        let mut config = ServerConfig::default();
+        if let Some((_, path)) = flags.iter().find(|(key, _)| key == "config") {
+            config.apply_file(path)?;
+        }
+
+        let cli_key_file = flags.iter().any(|(key, _)| key == "key-file");
+        let mut cli_keys = Vec::new();
+        let mut cli_hmac_keys = Vec::new();
+        for (key, value) in &flags {
+            match key.as_str() {
+                "config" => {}
+                "key" => cli_keys.push(parse_key_entry(value)?),
+                "hmac-key" => cli_hmac_keys.push(parse_hmac_key(value)?),
+                _ => config.apply(key, value)?,
+            }
+        }
+        if !cli_keys.is_empty() {
+            if cli_key_file {
+                return Err(ConfigError::InvalidKeyTable("--key and --key-file cannot be combined".to_string()));
+            }
+            config.secrets = key_table(cli_keys)?;
+            // The flags replace a key file from the config file too
+            config.key_file = None;
+        }
+        if !cli_hmac_keys.is_empty() {
+            config.hmac_keys = ordered(cli_hmac_keys)?;
+        }
+        if config.auth_mode == AuthMode::Hmac && config.hmac_keys.is_empty() {
+            return Err(ConfigError::InvalidKeyTable("the hmac mode needs [hmac_keys] or --hmac-key".to_string()));
+        }
+        if config.auth_mode == AuthMode::Hmac && (config.robot_keys.is_some() || config.key_file.is_some()) {
+            return Err(ConfigError::InvalidKeyTable("robot_keys and key_file only work in the classic mode".to_string()));
+        }
+        config.build_authenticator()?;
+
+        Ok(config)
+    }
+
+    /// Builds `authenticator` from the key settings, reading the key files right away
+    pub fn build_authenticator(&mut self) -> Result<(), ConfigError> {
+        self.authenticator = Arc::from(auth::build(self)?);
+        Ok(())
+    }
+
+    fn apply_file(&mut self, path: &str) -> Result<(), ConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+        let mut keys = Vec::new();
+        let mut hmac_keys = Vec::new();
+        let mut section = "server";
+
+        for (index, raw) in content.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = match line[1..line.len() - 1].trim() {
+                    "server" => "server",
+                    "keys" => "keys",
+                    "hmac_keys" => "hmac_keys",
+                    "targets" => "targets",
+                    "missions" => "missions",
+                    _ => return Err(ConfigError::Syntax { line: index + 1, content: raw.to_string() }),
+                };
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or(ConfigError::Syntax { line: index + 1, content: raw.to_string() })?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match section {
+                "keys" => keys.push(parse_key_pair(key, value)?),
+                "hmac_keys" => hmac_keys.push(parse_hmac_key(&format!("{}={}", key, value))?),
+                "targets" => {
+                    let target = parse_target(value)
+                        .ok_or(ConfigError::InvalidValue { key: format!("target of {}", key), value: value.to_string() })?;
+                    self.user_targets.insert(key.to_string(), target);
+                },
+                "missions" => {
+                    let mission = parse_mission(value)
+                        .ok_or(ConfigError::InvalidValue { key: format!("mission of {}", key), value: value.to_string() })?;
+                    self.user_missions.insert(key.to_string(), mission);
+                },
+                _ => self.apply(&key.replace('_', "-"), value)?,
+            }
+        }
+
+        if !keys.is_empty() {
+            self.secrets = key_table(keys)?;
+        }
+        if !hmac_keys.is_empty() {
+            self.hmac_keys = ordered(hmac_keys)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue { key: key.to_string(), value: value.to_string() };
+
+        match key {
+            "host" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.host = value.to_string()
+            },
+            "port" => self.port = value.parse().map_err(|_| invalid())?,
+            "timeout" => self.timeout_normal = parse_secs(value).ok_or_else(invalid)?,
+            "refill-timeout" => self.timeout_refilling = parse_secs(value).ok_or_else(invalid)?,
+            "log-level" => self.log_level = LogLevel::parse(value).ok_or_else(invalid)?,
+            "log-format" => self.log_format = LogFormat::parse(value).ok_or_else(invalid)?,
+            "runtime" => self.runtime = match value {
+                "threads" => Runtime::Threads,
+                "async" if cfg!(feature = "async") => Runtime::Async,
+                _ => return Err(invalid()),
+            },
+            "target" => self.target = parse_target(value).ok_or_else(invalid)?,
+            "user-target" => {
+                let (username, target) = value.split_once('=').ok_or_else(invalid)?;
+                let target = parse_target(target).ok_or_else(invalid)?;
+                self.user_targets.insert(username.to_string(), target);
+            },
+            "mission" => self.mission = Some(parse_mission(value).ok_or_else(invalid)?),
+            "user-mission" => {
+                let (username, mission) = value.split_once('=').ok_or_else(invalid)?;
+                let mission = parse_mission(mission).ok_or_else(invalid)?;
+                self.user_missions.insert(username.to_string(), mission);
+            },
+            "strategy" => self.strategy = Strategy::parse(value).ok_or_else(invalid)?,
+            "map" => self.map = match value {
+                "off" => None,
+                _ if value.is_empty() || value.contains(char::is_whitespace) => return Err(invalid()),
+                _ => Some(value.to_string()),
+            },
+            "map-file" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.map_file = Some(value.to_string())
+            },
+            "auth-mode" => self.auth_mode = AuthMode::parse(value).ok_or_else(invalid)?,
+            "key-file" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.key_file = Some(value.to_string())
+            },
+            "robot-keys" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.robot_keys = Some(value.to_string())
+            },
+            "allow-list" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.allow_list = Some(value.to_string())
+            },
+            "record" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.record_dir = Some(value.to_string())
+            },
+            "metrics" => {
+                if value.is_empty() {
+                    return Err(invalid());
+                }
+                self.metrics_addr = Some(value.to_string())
+            },
+            "lockout-after" => self.lockout_after = value.parse().map_err(|_| invalid())?,
+            "lockout" => self.lockout = parse_secs(value).ok_or_else(invalid)?,
+            "connection-rate" => self.connection_rate = value.parse().map_err(|_| invalid())?,
+            "grace-period" => self.grace_period = parse_secs(value).ok_or_else(invalid)?,
+            "step-budget" => self.step_budget = match value.parse() {
+                Ok(budget) if budget > 0 => budget,
+                _ => return Err(invalid()),
+            },
+            "max-sessions" => self.max_sessions = match value.parse() {
+                Ok(max) if max > 0 => max,
+                _ => return Err(invalid()),
+            },
+            _ => return Err(ConfigError::UnknownFlag(key.to_string())),
+        }
+        Ok(())
+    }
+}
+
+fn parse_flags<I: IntoIterator<Item = String>>(args: I) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+
+        let key = match flag.as_str() {
+            "-h" | "--help" => return Err(ConfigError::Help),
+            "-c" | "--config" => "config",
+            "-H" | "--host" => "host",
+            "-p" | "--port" => "port",
+            "--timeout" => "timeout",
+            "--refill-timeout" => "refill-timeout",
+            "--key" => "key",
+            "--key-file" => "key-file",
+            "--auth-mode" => "auth-mode",
+            "--hmac-key" => "hmac-key",
+            "--log-level" => "log-level",
+            "--log-format" => "log-format",
+            "--runtime" => "runtime",
+            "--max-sessions" => "max-sessions",
+            "--step-budget" => "step-budget",
+            "--grace-period" => "grace-period",
+            "--target" => "target",
+            "--user-target" => "user-target",
+            "--mission" => "mission",
+            "--user-mission" => "user-mission",
+            "--strategy" => "strategy",
+            "--map" => "map",
+            "--map-file" => "map-file",
+            "--robot-keys" => "robot-keys",
+            "--allow-list" => "allow-list",
+            "--lockout-after" => "lockout-after",
+            "--lockout" => "lockout",
+            "--connection-rate" => "connection-rate",
+            "--record" => "record",
+            "--metrics" => "metrics",
+            _ => return Err(ConfigError::UnknownFlag(arg)),
+        };
+
+        let value = match inline {
+            Some(value) => value,
+            None => args.next().ok_or(ConfigError::MissingValue(flag))?,
+        };
+        flags.push((key.to_string(), value));
+    }
+
+    Ok(flags)
+}
+
+fn parse_secs(value: &str) -> Option<Duration> {
+    let secs = value.parse::<f64>().ok()?;
+    if secs.is_finite() && secs > 0.0 {
+        Some(Duration::from_secs_f64(secs))
+    } else {
+        None
+    }
+}
+
+/// Parses coordinates written as `X,Y` or `X Y`.
+fn parse_target(value: &str) -> Option<(i32, i32)> {
+    let value = value.replace(',', " ");
+    let mut parts = value.split_whitespace();
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((x, y))
+}
+
+/// Parses waypoints separated by `;`, there has to be at least one.
+fn parse_mission(value: &str) -> Option<Mission> {
+    let waypoints = value.split(';')
+        .map(parse_target)
+        .collect::<Option<Vec<_>>>()?;
+    Some(Mission { waypoints })
+}
+
+/// Parses `ID=SERVER,CLIENT` as given on the command line.
+fn parse_key_entry(value: &str) -> Result<(usize, ServerSecret), ConfigError> {
+    let (id, pair) = value.split_once('=').ok_or(ConfigError::InvalidValue {
+        key: "key".to_string(), value: value.to_string()
+    })?;
+    parse_key_pair(id.trim(), &pair.replace(',', " "))
+}
+
+/// Parses one key table line, `ID = SERVER CLIENT`.
+pub(crate) fn parse_key_pair(id: &str, pair: &str) -> Result<(usize, ServerSecret), ConfigError> {
+    let invalid = || ConfigError::InvalidValue { key: format!("key {}", id), value: pair.to_string() };
+
+    let id = id.parse::<usize>().map_err(|_| invalid())?;
+    let mut parts = pair.split_whitespace();
+    let s = parts.next().and_then(|s| s.parse::<u32>().ok()).ok_or_else(invalid)?;
+    let c = parts.next().and_then(|c| c.parse::<u32>().ok()).ok_or_else(invalid)?;
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok((id, ServerSecret { s, c }))
+}
+
+/// Reads a key table file with one `ID = SERVER CLIENT` line per key.
+pub fn load_key_table(path: &str) -> Result<Vec<ServerSecret>, ConfigError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+
+    let mut keys = Vec::new();
+    for (index, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') || line == "[keys]" {
+            continue;
+        }
+        let (id, pair) = line.split_once('=')
+            .ok_or(ConfigError::Syntax { line: index + 1, content: raw.to_string() })?;
+        keys.push(parse_key_pair(id.trim(), pair.trim())?);
+    }
+
+    if keys.is_empty() {
+        return Err(ConfigError::InvalidKeyTable(format!("{} has no keys", path)));
+    }
+    key_table(keys)
+}
+
+/// Parses `ID=SECRET`, the secret cannot be empty.
+fn parse_hmac_key(value: &str) -> Result<(usize, String), ConfigError> {
+    let invalid = || ConfigError::InvalidValue { key: "hmac-key".to_string(), value: value.to_string() };
+
+    let (id, secret) = value.split_once('=').ok_or_else(invalid)?;
+    let id = id.trim().parse::<usize>().map_err(|_| invalid())?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return Err(invalid());
+    }
+    Ok((id, secret.to_string()))
+}
+
+/// Turns the loaded entries into a table indexed by key ID.
+/// IDs have to be unique and form the range 0..n, values have to fit the 16 bit hash.
+pub(crate) fn key_table(entries: Vec<(usize, ServerSecret)>) -> Result<Vec<ServerSecret>, ConfigError> {
+    let secrets = ordered(entries)?;
+    for (id, secret) in secrets.iter().enumerate() {
+        if secret.s >= 65536 || secret.c >= 65536 {
+            return Err(ConfigError::InvalidKeyTable(format!("key ID {} is out of 0..65536", id)));
+        }
+    }
+    Ok(secrets)
+}
+
+/// Orders the entries by key ID, the IDs have to be unique and form the range 0..n.
+fn ordered<T>(mut entries: Vec<(usize, T)>) -> Result<Vec<T>, ConfigError> {
+    entries.sort_by_key(|(id, _)| *id);
+
+    let mut values = Vec::new();
+    for (expected, (id, value)) in entries.into_iter().enumerate() {
+        if id < expected {
+            return Err(ConfigError::InvalidKeyTable(format!("duplicate key ID {}", id)));
+        }
+        if id > expected {
+            return Err(ConfigError::InvalidKeyTable(format!("missing key ID {}", expected)));
+        }
+        values.push(value);
+    }
+
+    Ok(values)
+}
@@ -1,9 +1,22 @@
 use std::time::Duration;
 
 
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 42069;
+pub const DEFAULT_MAX_SESSIONS: usize = 4096;
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+pub const DEFAULT_STEP_BUDGET: u32 = 1000;
+/// Failed logins in a row before an IP address or username is locked out
+pub const DEFAULT_LOCKOUT_AFTER: u32 = 5;
+pub const DEFAULT_LOCKOUT: Duration = Duration::from_secs(1);
+/// How often the key file is checked for changes
+pub const KEY_FILE_POLL: Duration = Duration::from_secs(1);
+/// How often the shared obstacles are saved to the map file if they changed
+pub const MAP_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 pub enum BTimeout { Normal, Refilling, }
 impl BTimeout {
-    pub fn value(&self) -> Duration {
+    pub fn default_value(&self) -> Duration {
         let secs = match self {
             Self::Normal => 1,
             Self::Refilling => 5,
@@ -12,6 +25,7 @@ impl BTimeout {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct ServerSecret {
     pub s: u32, 
     pub c: u32
@@ -27,4 +41,3 @@ impl ServerSecret {
         ]
     }
 }
-
@@ -1,18 +1,37 @@
+use std::fmt;
 use std::num::ParseIntError;
 
 use crate::messages::ServerMessage;
 
+/// Why the robot cannot get to its target
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unreachable {
+    /// The known obstacles leave no way to the target
+    NoPath { target: (i32, i32), obstacles: usize },
+    /// The robot used up the step budget
+    StepBudget(u32),
+}
+
 #[derive(Debug)]
 pub enum BError {
     Io(std::io::Error),
+    WriteFailed(std::io::Error),
     ConnectionClosed,
 
     MessageToLong(String, usize),
+    InvalidUtf8(Vec<u8>),
     FailedToParseNumber(Option<ParseIntError>),
     FailedToSplit,
 
+    UnknownRobot(String),
     InvalidKeyIndex(i32),
     HashMismatch{expected: u32, actual: u32},
+    InvalidConfirmation(i32),
+    AnswerMismatch,
+
+    ImpossibleMove{from: (i32, i32), to: (i32, i32)},
+    Enclosed((i32, i32)),
+    Unreachable(Unreachable),
 
     MessageWhileCharging,
     ChargingInCharging,
@@ -20,24 +39,58 @@ pub enum BError {
 }
 
 impl BError {
-    pub fn should_send(&self) -> bool {
+    /// The variant name, used as a metrics label
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Io(_) | Self::ConnectionClosed => false,
-            _ => true,
+            Self::Io(_) => "Io",
+            Self::WriteFailed(_) => "WriteFailed",
+            Self::ConnectionClosed => "ConnectionClosed",
+
+            Self::MessageToLong(_, _) => "MessageToLong",
+            Self::InvalidUtf8(_) => "InvalidUtf8",
+            Self::FailedToParseNumber(_) => "FailedToParseNumber",
+            Self::FailedToSplit => "FailedToSplit",
+
+            Self::UnknownRobot(_) => "UnknownRobot",
+            Self::InvalidKeyIndex(_) => "InvalidKeyIndex",
+            Self::HashMismatch {..} => "HashMismatch",
+            Self::InvalidConfirmation(_) => "InvalidConfirmation",
+            Self::AnswerMismatch => "AnswerMismatch",
+
+            Self::ImpossibleMove {..} => "ImpossibleMove",
+            Self::Enclosed(_) => "Enclosed",
+            Self::Unreachable(_) => "Unreachable",
+
+            Self::MessageWhileCharging => "MessageWhileCharging",
+            Self::ChargingInCharging => "ChargingInCharging",
+            Self::ChargingFullInvalidState => "ChargingFullInvalidState",
         }
     }
 
+    pub fn should_send(&self) -> bool {
+        !matches!(self, Self::Io(_) | Self::WriteFailed(_) | Self::ConnectionClosed)
+    }
+
     pub fn server_response(&self) -> ServerMessage {
         match self {
             Self::Io(_) => ServerMessage::Empty,
+            Self::WriteFailed(_) => ServerMessage::Empty,
             Self::ConnectionClosed => ServerMessage::Empty,
 
             Self::MessageToLong(_, _) => ServerMessage::SyntaxError,
+            Self::InvalidUtf8(_) => ServerMessage::SyntaxError,
             Self::FailedToParseNumber(_) => ServerMessage::SyntaxError,
             Self::FailedToSplit => ServerMessage::SyntaxError,
 
+            Self::UnknownRobot(_) => ServerMessage::LoginFailed,
             Self::InvalidKeyIndex(_) => ServerMessage::KeyOutOfRangeError,
             Self::HashMismatch {..} => ServerMessage::LoginFailed,
+            Self::InvalidConfirmation(_) => ServerMessage::LoginFailed,
+            Self::AnswerMismatch => ServerMessage::LoginFailed,
+
+            Self::ImpossibleMove {..} => ServerMessage::LogicError,
+            Self::Enclosed(_) => ServerMessage::Logout,
+            Self::Unreachable(_) => ServerMessage::Logout,
 
             Self::MessageWhileCharging => ServerMessage::LogicError,
             Self::ChargingInCharging => ServerMessage::LogicError,
@@ -46,3 +99,35 @@ impl BError {
     }
 }
 
+impl fmt::Display for BError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::WriteFailed(e) => write!(f, "Failed to send a message: {}", e),
+            Self::ConnectionClosed => write!(f, "Connection closed by the client"),
+
+            Self::MessageToLong(message, len) => write!(f, "Message \"{}\" is too long ({} bytes)", message, len),
+            Self::InvalidUtf8(bytes) => write!(f, "Message {:?} is not valid UTF-8", bytes),
+            Self::FailedToParseNumber(Some(e)) => write!(f, "Failed to parse a number: {}", e),
+            Self::FailedToParseNumber(None) => write!(f, "Failed to parse a number"),
+            Self::FailedToSplit => write!(f, "Failed to split the message"),
+
+            Self::UnknownRobot(username) => write!(f, "Robot \"{}\" is not allowed to log in", username),
+            Self::InvalidKeyIndex(key) => write!(f, "Invalid key index {}", key),
+            Self::HashMismatch { expected, actual } => write!(f, "Hash mismatch, expected {}, got {}", expected, actual),
+            Self::InvalidConfirmation(hash) => write!(f, "Confirmation {} is out of range", hash),
+            Self::AnswerMismatch => write!(f, "HMAC answer does not match the nonce"),
+
+            Self::ImpossibleMove { from, to } => write!(f, "Robot cannot move from {:?} to {:?} in one step", from, to),
+            Self::Enclosed(position) => write!(f, "Robot is blocked in every direction at {:?}", position),
+            Self::Unreachable(Unreachable::NoPath { target, obstacles }) =>
+                write!(f, "Target {:?} is unreachable, {} known obstacles block every path", target, obstacles),
+            Self::Unreachable(Unreachable::StepBudget(budget)) =>
+                write!(f, "Target not reached within {} commands", budget),
+
+            Self::MessageWhileCharging => write!(f, "Message received while charging"),
+            Self::ChargingInCharging => write!(f, "Recharging while already charging"),
+            Self::ChargingFullInvalidState => write!(f, "Full power while not charging"),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::io;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::errors::BError;
+
+/// Random bytes in the nonce, sent as twice as many hex digits
+const NONCE_BYTES: usize = 8;
+/// Hex digits of the robot's answer, a SHA-256 HMAC
+pub const ANSWER_LEN: usize = 64;
+
+/// How the robots prove they know the key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMode {
+    /// The assignment's confirmations, a username byte sum plus the key pair
+    Classic,
+    /// The server sends a random nonce, the robot answers with
+    /// the hex HMAC-SHA256 of the username followed by the nonce, keyed by the secret of its key index
+    Hmac,
+}
+
+impl AuthMode {
+
+    pub fn parse(value: &str) -> Option<AuthMode> {
+        match value {
+            "classic" => Some(Self::Classic),
+            "hmac" => Some(Self::Hmac),
+            _ => None,
+        }
+    }
+}
+
+/// A fresh nonce from the OS random generator
+pub fn nonce() -> Result<String, BError> {
+    let mut bytes = [0; NONCE_BYTES];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| BError::Io(io::Error::other(e.to_string())))?;
+    Ok(to_hex(&bytes))
+}
+
+/// Whether the message looks like a nonce sent by `nonce`
+pub fn is_nonce(message: &str) -> bool {
+    message.len() == 2 * NONCE_BYTES && message.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// The answer a robot with the secret sends for the nonce
+pub fn answer(secret: &str, username: &str, nonce: &str) -> String {
+    to_hex(&mac(secret, username, nonce).finalize().into_bytes())
+}
+
+/// Checks the robot's answer in constant time
+pub fn verify(secret: &str, username: &str, nonce: &str, answer: &str) -> bool {
+    match from_hex(answer) {
+        Some(bytes) => mac(secret, username, nonce).verify_slice(&bytes).is_ok(),
+        None => false,
+    }
+}
+
+fn mac(secret: &str, username: &str, nonce: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    mac.update(nonce.as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != ANSWER_LEN || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,57 @@
+//! Server side of the BI-PSI robot protocol.
+//!
+//! The protocol engine ([`BState`] and [`PathState`]) is independent of the network,
+//! every received [`ClientMessage`] is turned into the next state and a [`PRes`] action.
+//! [`handle_server`] drives one TCP connection using it and [`serve`] runs the listener,
+//! with the `async` feature `async_server` does the same using tokio tasks.
+//! [`RobotClient`] is the robot side of the protocol.
+//!
+//! The public modules are the tools built on top of it: key backends, the HMAC login,
+//! logging, metrics, the shared obstacle map, transcripts, the simulator and the grid drawing.
+
+#[macro_use]
+pub mod logger;
+
+#[cfg(feature = "async")]
+pub mod async_server;
+pub mod auth;
+pub mod config;
+pub mod hmac_auth;
+pub mod metrics;
+pub mod obstacles;
+pub mod simulator;
+pub mod transcript;
+pub mod visualize;
+
+mod axis;
+mod client;
+mod codec;
+mod constants;
+mod errors;
+mod lockout;
+mod messages;
+mod mission;
+mod navigation;
+mod observer;
+mod path;
+mod planner;
+mod server;
+mod shutdown;
+mod state_machine;
+mod stats;
+
+pub use axis::AxisStrategy;
+pub use client::{ClientError, RobotClient};
+pub use codec::FrameDecoder;
+pub use config::ServerConfig;
+pub use constants::ServerSecret;
+pub use errors::{BError, Unreachable};
+pub use messages::{ClientMessage, ServerMessage};
+pub use mission::Mission;
+pub use navigation::{NavigationStrategy, Step, Strategy};
+pub use path::{Orient, PathState};
+pub use planner::Planner;
+pub use server::{handle_server, serve};
+pub use shutdown::{SessionGuard, Shutdown, Verdict};
+pub use state_machine::{BState, PRes};
+pub use stats::PathStats;
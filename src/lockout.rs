@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::messages::ClientMessage;
+use crate::metrics;
+use crate::state_machine::BState;
+
+/// Lockouts never get longer than this
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Entries kept before the ones no longer locked out are forgotten
+const MAX_ENTRIES: usize = 10_000;
+
+/// Who failed to log in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    Ip(IpAddr),
+    Username(String),
+}
+
+#[derive(Debug, Default)]
+struct Failures {
+    count: u32,
+    until: Option<Instant>,
+}
+
+/// Counts failed logins per IP address and per username.
+///
+/// After `threshold` failures in a row the subject is locked out for `base`,
+/// every further failure doubles the lockout. A successful login forgets the failures.
+pub struct Lockout {
+    failures: Mutex<HashMap<Subject, Failures>>,
+}
+
+impl Lockout {
+
+    pub fn new() -> Lockout {
+        Lockout { failures: Mutex::new(HashMap::new()) }
+    }
+
+    fn failures(&self) -> MutexGuard<'_, HashMap<Subject, Failures>> {
+        self.failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// How long the subject stays locked out
+    pub fn locked(&self, subject: &Subject) -> Option<Duration> {
+        let until = self.failures().get(subject)?.until?;
+        until.checked_duration_since(Instant::now())
+    }
+
+    /// Counts a failure, returns the lockout it started, `threshold` 0 never locks anybody out
+    pub fn failed(&self, subject: Subject, threshold: u32, base: Duration) -> Option<Duration> {
+        if threshold == 0 {
+            return None;
+        }
+
+        let mut failures = self.failures();
+        if failures.len() >= MAX_ENTRIES {
+            let now = Instant::now();
+            failures.retain(|_, entry| entry.until.is_some_and(|until| until > now));
+        }
+
+        let entry = failures.entry(subject).or_default();
+        entry.count += 1;
+        if entry.count < threshold {
+            return None;
+        }
+        let doublings = (entry.count - threshold).min(16);
+        let lockout = base.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+        entry.until = Some(Instant::now() + lockout);
+        Some(lockout)
+    }
+
+    pub fn succeeded(&self, subject: &Subject) {
+        self.failures().remove(subject);
+    }
+}
+
+impl Default for Lockout {
+    fn default() -> Self {
+        Lockout::new()
+    }
+}
+
+/// The failed logins of every session on the server
+pub fn shared() -> &'static Lockout {
+    static SHARED: OnceLock<Lockout> = OnceLock::new();
+    SHARED.get_or_init(Lockout::new)
+}
+
+/// Token bucket limiting the new connections per second, with bursts up to the rate
+pub struct RateLimit {
+    rate: u32,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimit {
+
+    /// `rate` 0 allows everything
+    pub fn new(rate: u32) -> RateLimit {
+        RateLimit { rate, tokens: f64::from(rate), last: Instant::now() }
+    }
+
+    pub fn allow(&mut self) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let rate = f64::from(self.rate);
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * rate).min(rate);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Whether a new connection should be closed before its session starts
+pub fn reject_connection(rate: &mut RateLimit, peer: SocketAddr) -> bool {
+    if !rate.allow() {
+        info!("Connection rate exceeded, closing {}", peer);
+        metrics::blocked("rate");
+        return true;
+    }
+    if let Some(left) = shared().locked(&Subject::Ip(peer.ip())) {
+        info!("{} is locked out for {:.1}s, closing", peer.ip(), left.as_secs_f64());
+        metrics::blocked("ip");
+        return true;
+    }
+    false
+}
+
+/// Whether the message is a username that is locked out, checked before the state machine sees it
+pub fn reject_username(state: &BState, message: &ClientMessage) -> bool {
+    if !matches!(state, BState::LoginUsername) {
+        return false;
+    }
+    match shared().locked(&Subject::Username(message.0.clone())) {
+        Some(left) => {
+            info!("Username \"{}\" is locked out for {:.1}s, closing", message.0, left.as_secs_f64());
+            metrics::blocked("username");
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::thread;
+
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn ip() -> Subject {
+        Subject::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+    }
+
+    #[test]
+    fn lockout_doubles_after_threshold() {
+        let lockout = Lockout::new();
+        assert_eq!(lockout.failed(ip(), 3, SECOND), None);
+        assert_eq!(lockout.failed(ip(), 3, SECOND), None);
+        assert_eq!(lockout.locked(&ip()), None);
+
+        assert_eq!(lockout.failed(ip(), 3, SECOND), Some(SECOND));
+        assert_eq!(lockout.failed(ip(), 3, SECOND), Some(2 * SECOND));
+        assert_eq!(lockout.failed(ip(), 3, SECOND), Some(4 * SECOND));
+
+        let left = lockout.locked(&ip()).unwrap();
+        assert!(left > 3 * SECOND && left <= 4 * SECOND, "{:?}", left);
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let lockout = Lockout::new();
+        let mut last = Duration::ZERO;
+        for _ in 0..40 {
+            let current = lockout.failed(ip(), 1, 100 * SECOND).unwrap();
+            assert!(current >= last);
+            last = current;
+        }
+        assert_eq!(last, 15 * 60 * SECOND);
+    }
+
+    #[test]
+    fn success_forgets_failures() {
+        let lockout = Lockout::new();
+        let username = Subject::Username("Oompa Loompa".to_string());
+        for _ in 0..3 {
+            lockout.failed(username.clone(), 3, SECOND);
+            lockout.failed(ip(), 3, SECOND);
+        }
+        assert!(lockout.locked(&username).is_some());
+
+        lockout.succeeded(&username);
+        assert_eq!(lockout.locked(&username), None);
+        assert!(lockout.locked(&ip()).is_some());
+
+        // Counting starts over
+        assert_eq!(lockout.failed(username.clone(), 3, SECOND), None);
+        assert_eq!(lockout.failed(username, 3, SECOND), None);
+    }
+
+    #[test]
+    fn zero_threshold_never_locks_out() {
+        let lockout = Lockout::new();
+        for _ in 0..10 {
+            assert_eq!(lockout.failed(ip(), 0, SECOND), None);
+        }
+        assert_eq!(lockout.locked(&ip()), None);
+    }
+
+    #[test]
+    fn rate_limit_refills() {
+        let mut rate = RateLimit::new(2);
+        assert!(rate.allow());
+        assert!(rate.allow());
+        assert!(!rate.allow());
+
+        // 1.2 tokens, not enough for two connections
+        thread::sleep(Duration::from_millis(600));
+        assert!(rate.allow());
+        assert!(!rate.allow());
+
+        let mut unlimited = RateLimit::new(0);
+        assert!((0..1000).all(|_| unlimited.allow()));
+    }
+}
@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum LogLevel { Error, Warn, Info, Debug, }
+
+impl LogLevel {
+    pub fn parse(str: &str) -> Option<LogLevel> {
+        match str.to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat { Text, Json, }
+
+impl LogFormat {
+    pub fn parse(str: &str) -> Option<LogFormat> {
+        match str.to_ascii_lowercase().as_str() {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies the connection a log line belongs to
+#[derive(Debug, Clone)]
+pub struct LogContext {
+    pub session: u64,
+    pub peer: Option<SocketAddr>,
+}
+
+impl LogContext {
+    /// Creates a context with a new unique session ID
+    pub fn new(peer: Option<SocketAddr>) -> LogContext {
+        static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+        LogContext { session: NEXT_SESSION.fetch_add(1, Ordering::Relaxed), peer }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Debug as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
+
+#[cfg(feature = "async")]
+tokio::task_local! {
+    /// Context of an async session, tasks can move between threads
+    pub static TASK_CONTEXT: LogContext;
+}
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Attaches the context to all the lines logged from this thread
+/// until the returned guard is dropped
+pub fn enter(context: LogContext) -> ContextGuard {
+    let previous = CONTEXT.with(|cell| cell.replace(Some(context)));
+    ContextGuard { previous }
+}
+
+pub struct ContextGuard {
+    previous: Option<LogContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CONTEXT.with(|cell| cell.replace(previous));
+    }
+}
+
+pub fn current_context() -> Option<LogContext> {
+    #[cfg(feature = "async")]
+    if let Ok(context) = TASK_CONTEXT.try_with(Clone::clone) {
+        return Some(context);
+    }
+    CONTEXT.with(|cell| cell.borrow().clone())
+}
+
+pub fn log(level: LogLevel, target: &str, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    let format = if JSON.load(Ordering::Relaxed) { LogFormat::Json } else { LogFormat::Text };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or(0);
+    println!("{}", format_line(format, level, target, current_context().as_ref(), timestamp, args));
+}
+
+/// One log line without the line break, `timestamp` is in unix millis and only written in JSON
+pub fn format_line(format: LogFormat, level: LogLevel, target: &str, context: Option<&LogContext>, timestamp: u128, args: fmt::Arguments) -> String {
+    match format {
+        LogFormat::Text => text_line(level, context, args),
+        LogFormat::Json => json_line(level, target, context, timestamp, args),
+    }
+}
+
+fn text_line(level: LogLevel, context: Option<&LogContext>, args: fmt::Arguments) -> String {
+    let mut line = format!("{:5} ", level.name().to_ascii_uppercase());
+    if let Some(context) = context {
+        match context.peer {
+            Some(peer) => write!(line, "[#{} {}] ", context.session, peer),
+            None => write!(line, "[#{}] ", context.session),
+        }.unwrap();
+    }
+    write!(line, "{}", args).unwrap();
+    line
+}
+
+fn json_line(level: LogLevel, target: &str, context: Option<&LogContext>, timestamp: u128, args: fmt::Arguments) -> String {
+    let mut line = format!("{{\"ts\":{},\"level\":\"{}\",\"target\":", timestamp, level.name());
+    push_json_str(&mut line, target);
+    if let Some(context) = context {
+        write!(line, ",\"session\":{}", context.session).unwrap();
+        if let Some(peer) = context.peer {
+            line.push_str(",\"peer\":");
+            push_json_str(&mut line, &peer.to_string());
+        }
+    }
+    line.push_str(",\"msg\":");
+    push_json_str(&mut line, &args.to_string());
+    line.push('}');
+    line
+}
+
+fn push_json_str(out: &mut String, str: &str) {
+    out.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::LogLevel::Error, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::LogLevel::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::LogLevel::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::LogLevel::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(peer: Option<&str>) -> LogContext {
+        LogContext { session: 7, peer: peer.map(|peer| peer.parse().unwrap()) }
+    }
+
+    #[test]
+    fn text_lines_carry_the_context() {
+        let line = format_line(LogFormat::Text, LogLevel::Info, "bobika::server", None, 0, format_args!("Starting {}", 1));
+        assert_eq!(line, "INFO  Starting 1");
+
+        let peer = context(Some("127.0.0.1:51234"));
+        let line = format_line(LogFormat::Text, LogLevel::Debug, "bobika::server", Some(&peer), 0, format_args!("> Read: OK 1 2"));
+        assert_eq!(line, "DEBUG [#7 127.0.0.1:51234] > Read: OK 1 2");
+
+        let line = format_line(LogFormat::Text, LogLevel::Warn, "bobika::server", Some(&context(None)), 0, format_args!("x"));
+        assert_eq!(line, "WARN  [#7] x");
+    }
+
+    #[test]
+    fn json_lines_carry_the_context() {
+        let peer = context(Some("[::1]:4000"));
+        let line = format_line(LogFormat::Json, LogLevel::Error, "bobika::path", Some(&peer), 1700000000123, format_args!("Enclosed"));
+        assert_eq!(line, r#"{"ts":1700000000123,"level":"error","target":"bobika::path","session":7,"peer":"[::1]:4000","msg":"Enclosed"}"#);
+
+        let line = format_line(LogFormat::Json, LogLevel::Info, "bobika", None, 5, format_args!("Stopped"));
+        assert_eq!(line, r#"{"ts":5,"level":"info","target":"bobika","msg":"Stopped"}"#);
+    }
+
+    #[test]
+    fn json_escapes_the_message() {
+        let message = "Read: \"Oompa\\Loompa\"\n\r\t\x07\x08\x1f é";
+        let line = format_line(LogFormat::Json, LogLevel::Debug, "t", None, 0, format_args!("{}", message));
+        assert_eq!(line, r#"{"ts":0,"level":"debug","target":"t","msg":"Read: \"Oompa\\Loompa\"\n\r\t\u0007\u0008\u001f é"}"#);
+    }
+}
@@ -1,30 +1,213 @@
-use std::{thread, net::TcpListener};
+use std::sync::atomic::AtomicBool;
+use std::{fs, process, sync::Arc};
 
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
 
-mod constants;
-mod errors;
-mod state_machine;
-mod messages;
-mod server;
-mod path;
+use bobika::config::{self, ConfigError, Runtime, ServerConfig};
+use bobika::simulator::{self, Outcome};
+use bobika::transcript::Entry;
+use bobika::visualize::Track;
+use bobika::{auth, error, info, logger, metrics, obstacles, serve, transcript, Shutdown};
 
 fn main() {
-    let host = "127.0.0.1";
-    let port = 42069;
-    // let addr = host.to_owned() + ":" + &port.to_string();
-    let addr = format!("{}:{}", host, port);
-    let listener = TcpListener::bind(addr.clone()).unwrap();
-
-    println!("Starting Bobika!");
-    println!("Listening on {addr}");
-
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        thread::spawn(move || {
-            println!("Connection established!");
-            server::handle_server(stream);
-            println!("Connection closed!");
-        });
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("replay") {
+        args.remove(0);
+        replay(args);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("visualize") {
+        args.remove(0);
+        visualize(args);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("simulate") {
+        args.remove(0);
+        simulate(args);
+        return;
+    }
+
+    let config = load_config(args);
+    if let Some(dir) = &config.record_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Error: cannot create the transcript directory {}: {}", dir, e);
+            process::exit(2);
+        }
+    }
+
+    if let Some(addr) = &config.metrics_addr {
+        if let Err(e) = metrics::serve(addr) {
+            eprintln!("Error: cannot bind the metrics listener {}: {}", addr, e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = &config.map_file {
+        if let Err(e) = obstacles::shared().load(path) {
+            eprintln!("Error: cannot load the obstacle map {}: {}", path, e);
+            process::exit(2);
+        }
+        obstacles::autosave(path.clone());
+    }
+
+    let shutdown = Arc::new(Shutdown::new(config.grace_period));
+    for signal in [SIGINT, SIGTERM] {
+        // The second signal kills the server without waiting
+        signal_hook::flag::register_conditional_shutdown(signal, 1, shutdown.flag())
+            .and_then(|_| signal_hook::flag::register(signal, shutdown.flag()))
+            .expect("Failed to register a signal handler");
+    }
+
+    let hangup = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&hangup))
+        .expect("Failed to register a signal handler");
+    auth::watch(Arc::clone(&config.authenticator), hangup);
+
+    info!("Starting Bobika!");
+
+    let config = Arc::new(config);
+    let res = match config.runtime {
+        Runtime::Threads => serve(Arc::clone(&config), Arc::clone(&shutdown)),
+        Runtime::Async => run_async(Arc::clone(&config), Arc::clone(&shutdown)),
+    };
+    if let Err(e) = res {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+
+    let (drained, aborted) = shutdown.report();
+    info!("Stopped, {} sessions drained, {} aborted", drained, aborted);
+    info!("Paths: {}", metrics::path_report());
+
+    if let Some(path) = &config.map_file {
+        match obstacles::shared().save(path) {
+            Ok(()) => info!("Obstacles saved to {}", path),
+            Err(e) => error!("Failed to save the obstacles to {}: {}", path, e),
+        }
     }
 }
 
+fn load_config(args: Vec<String>) -> ServerConfig {
+    let config = match ServerConfig::from_args(args) {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            println!("{}", config::USAGE);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    };
+    logger::set_level(config.log_level);
+    logger::set_format(config.log_format);
+    config
+}
+
+/// `bobika replay <TRANSCRIPT> [OPTIONS]` runs a recorded session through the state machine again
+fn replay(mut args: Vec<String>) {
+    if args.is_empty() || args[0].starts_with('-') {
+        eprintln!("Usage: bobika replay <TRANSCRIPT> [OPTIONS]");
+        process::exit(2);
+    }
+    let path = args.remove(0);
+    let config = load_config(args);
+
+    let records = match transcript::load(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Error: cannot load {}: {}", path, e);
+            process::exit(2);
+        }
+    };
+
+    let report = transcript::replay(&records, &config);
+    for mismatch in &report.mismatches {
+        println!("Step {}: client sent \"{}\"", mismatch.step, transcript::escape(&mismatch.client));
+        println!("  - recorded: {:?}", mismatch.expected);
+        println!("  + replayed: {:?}", mismatch.actual);
+    }
+    println!("Replayed {} client messages, {} mismatches", report.steps, report.mismatches.len());
+
+    if !report.mismatches.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// `bobika visualize <TRANSCRIPT> [OPTIONS]` draws the grid a recorded session explored
+fn visualize(mut args: Vec<String>) {
+    if args.is_empty() || args[0].starts_with('-') {
+        eprintln!("Usage: bobika visualize <TRANSCRIPT> [OPTIONS]");
+        process::exit(2);
+    }
+    let path = args.remove(0);
+    let config = load_config(args);
+
+    let records = match transcript::load(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Error: cannot load {}: {}", path, e);
+            process::exit(2);
+        }
+    };
+
+    let mut track = Track::new();
+    for record in &records {
+        match &record.entry {
+            Entry::Client(message) => track.client(message),
+            Entry::Server(message) => track.server(message),
+            Entry::Closed(_) => break,
+        }
+    }
+
+    let targets = match &track.username {
+        Some(username) => config.mission_for(username).waypoints,
+        None => Vec::new(),
+    };
+    print!("{}", track.render(&targets));
+}
+
+/// `bobika simulate [SCENARIO...] [OPTIONS]` plays robots against a running server
+fn simulate(mut args: Vec<String>) {
+    let split = args.iter().position(|arg| arg.starts_with('-')).unwrap_or(args.len());
+    let names: Vec<String> = args.drain(..split).collect();
+    let config = load_config(args);
+
+    let scenarios: Vec<_> = simulator::scenarios().into_iter()
+        .filter(|scenario| names.is_empty() || names.contains(&scenario.name))
+        .collect();
+    if let Some(unknown) = names.iter().find(|name| !scenarios.iter().any(|s| &&s.name == name)) {
+        let known: Vec<_> = simulator::scenarios().into_iter().map(|s| s.name).collect();
+        eprintln!("Error: unknown scenario {}, available: {}", unknown, known.join(", "));
+        process::exit(2);
+    }
+
+    let mut failed = 0;
+    for scenario in &scenarios {
+        match simulator::run(&config, scenario) {
+            Outcome::Passed { commands } => println!("PASS {} ({} commands)", scenario.name, commands),
+            Outcome::Failed(reason) => {
+                failed += 1;
+                println!("FAIL {}: {}", scenario.name, reason);
+            }
+        }
+    }
+    println!("{} passed, {} failed", scenarios.len() - failed, failed);
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+#[cfg(feature = "async")]
+fn run_async(config: Arc<ServerConfig>, shutdown: Arc<Shutdown>) -> std::io::Result<()> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(bobika::async_server::serve(config, shutdown))
+}
+
+#[cfg(not(feature = "async"))]
+fn run_async(_: Arc<ServerConfig>, _: Arc<Shutdown>) -> std::io::Result<()> {
+    unreachable!("The async runtime is rejected by the config without the async feature");
+}
@@ -2,10 +2,21 @@
 #[derive(Debug)]
 pub struct ClientMessage(pub String);
 
+impl ClientMessage {
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut bytes = self.0.clone().into_bytes();
+        bytes.push(7); // \a
+        bytes.push(8); // \b
+        bytes
+    }
+}
 
-#[derive(Debug)]
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ServerMessage {
     Confirm(u32),
+    /// The HMAC login challenge
+    Nonce(String),
     Move,
     Left,
     Right,
@@ -21,9 +32,11 @@ pub enum ServerMessage {
 }
 
 impl ServerMessage {
-    pub fn to_payload(&self) -> Vec<u8> {
-        let body = match self {
+    /// The message without the terminator
+    pub fn body(&self) -> String {
+        match self {
             Self::Confirm(key) => key.to_string(),
+            Self::Nonce(nonce) => nonce.clone(),
             Self::Move => "102 MOVE".to_string(),
             Self::Left => "103 TURN LEFT".to_string(),
             Self::Right => "104 TURN RIGHT".to_string(),
@@ -36,8 +49,32 @@ impl ServerMessage {
             Self::LogicError => "302 LOGIC ERROR".to_string(),
             Self::KeyOutOfRangeError => "303 KEY OUT OF RANGE".to_string(),
             Self::Empty => "".to_string(),
+        }
+    }
+
+    /// The message with the body, the server confirmation for any number.
+    /// A nonce cannot be told apart from other bodies, it is `None`.
+    pub fn parse(body: &str) -> Option<ServerMessage> {
+        let message = match body {
+            "102 MOVE" => Self::Move,
+            "103 TURN LEFT" => Self::Left,
+            "104 TURN RIGHT" => Self::Right,
+            "105 GET MESSAGE" => Self::PickUp,
+            "106 LOGOUT" => Self::Logout,
+            "107 KEY REQUEST" => Self::KeyRequest,
+            "200 OK" => Self::NoProblemo,
+            "300 LOGIN FAILED" => Self::LoginFailed,
+            "301 SYNTAX ERROR" => Self::SyntaxError,
+            "302 LOGIC ERROR" => Self::LogicError,
+            "303 KEY OUT OF RANGE" => Self::KeyOutOfRangeError,
+            "" => Self::Empty,
+            _ => Self::Confirm(body.parse().ok()?),
         };
-        let mut bytes = body.as_bytes().to_vec();
+        Some(message)
+    }
+
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut bytes = self.body().into_bytes();
         bytes.push(7); // \a
         bytes.push(8); // \b
         bytes
@@ -0,0 +1,401 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::constants::BTimeout;
+use crate::errors::BError;
+use crate::messages::{ClientMessage, ServerMessage};
+use crate::stats::PathStats;
+
+const MOVES_BUCKETS: &[f64] = &[5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+const RECHARGE_BUCKETS: &[f64] = &[0.5, 1.0, 2.0, 3.0, 4.0, 5.0];
+const EXTRA_BUCKETS: &[f64] = &[0.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+const SIZE_BUCKETS: &[f64] = &[4.0, 8.0, 12.0, 20.0, 50.0, 100.0];
+const HTTP_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name).unwrap();
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count).unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count).unwrap();
+        writeln!(out, "{}_sum {}\n{}_count {}", name, self.sum, name, self.count).unwrap();
+    }
+}
+
+struct Registry {
+    accepted: u64,
+    completed: u64,
+    failed: BTreeMap<&'static str, u64>,
+    login_failures: BTreeMap<usize, u64>,
+    error_responses: BTreeMap<String, u64>,
+    recharges: u64,
+    blocked: BTreeMap<&'static str, u64>,
+    lockouts: u64,
+    path: PathTotals,
+    moves: Histogram,
+    extra_commands: Histogram,
+    recharge_duration: Histogram,
+    message_size: Histogram,
+}
+
+impl Registry {
+    fn new() -> Registry {
+        Registry {
+            accepted: 0,
+            completed: 0,
+            failed: BTreeMap::new(),
+            login_failures: BTreeMap::new(),
+            error_responses: BTreeMap::new(),
+            recharges: 0,
+            blocked: BTreeMap::new(),
+            lockouts: 0,
+            path: PathTotals::default(),
+            moves: Histogram::new(MOVES_BUCKETS),
+            extra_commands: Histogram::new(EXTRA_BUCKETS),
+            recharge_duration: Histogram::new(RECHARGE_BUCKETS),
+            message_size: Histogram::new(SIZE_BUCKETS),
+        }
+    }
+
+    fn finished(&mut self, moves: u64, stats: &PathStats) {
+        self.completed += 1;
+        self.moves.observe(moves as f64);
+        self.extra_commands.observe(stats.extra() as f64);
+        self.path.add(stats);
+    }
+
+    fn failed(&mut self, label: &'static str, login_key: Option<usize>) {
+        *self.failed.entry(label).or_default() += 1;
+        if let Some(key) = login_key {
+            *self.login_failures.entry(key).or_default() += 1;
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        counter(&mut out, "bobika_sessions_accepted_total", "Accepted connections", self.accepted);
+        counter(&mut out, "bobika_sessions_completed_total", "Sessions that picked up the message", self.completed);
+        labeled(&mut out, "bobika_sessions_failed_total", "Sessions ended by an error", "error",
+            self.failed.iter().map(|(label, count)| (label.to_string(), *count)));
+        labeled(&mut out, "bobika_login_failures_total", "Failed logins by key index", "key",
+            self.login_failures.iter().map(|(key, count)| (key.to_string(), *count)));
+        labeled(&mut out, "bobika_error_responses_total", "Error messages sent to robots", "response",
+            self.error_responses.iter().map(|(body, count)| (body.clone(), *count)));
+        counter(&mut out, "bobika_recharges_total", "Times robots started recharging", self.recharges);
+        labeled(&mut out, "bobika_blocked_total", "Connections and logins refused before the login", "reason",
+            self.blocked.iter().map(|(reason, count)| (reason.to_string(), *count)));
+        counter(&mut out, "bobika_lockouts_total", "IP addresses and usernames locked out after failed logins", self.lockouts);
+        counter(&mut out, "bobika_path_moves_total", "Moves sent in sessions that picked up the message", self.path.moves);
+        counter(&mut out, "bobika_path_turns_total", "Turns sent in sessions that picked up the message", self.path.turns);
+        counter(&mut out, "bobika_path_blocked_moves_total", "Moves that hit an obstacle in sessions that picked up the message", self.path.blocked);
+        counter(&mut out, "bobika_path_minimum_moves_total", "Manhattan distance of the start positions in sessions that picked up the message", self.path.distance);
+
+        self.moves.render(&mut out, "bobika_moves_per_extraction", "Move commands sent in sessions that picked up the message");
+        self.extra_commands.render(&mut out, "bobika_path_extra_commands", "Commands sent on top of the Manhattan distance of the start position");
+        self.recharge_duration.render(&mut out, "bobika_recharge_duration_seconds", "Time between RECHARGING and FULL POWER");
+        self.message_size.render(&mut out, "bobika_message_size_bytes", "Size of client messages including the terminator");
+
+        out
+    }
+}
+
+/// Path stats summed over all the sessions that picked up the message
+#[derive(Default)]
+struct PathTotals {
+    moves: u64,
+    turns: u64,
+    blocked: u64,
+    distance: u64,
+}
+
+impl PathTotals {
+
+    fn add(&mut self, stats: &PathStats) {
+        self.moves += u64::from(stats.moves);
+        self.turns += u64::from(stats.turns);
+        self.blocked += u64::from(stats.blocked);
+        self.distance += u64::from(stats.distance.unwrap_or(0));
+    }
+
+    fn report(&self, completed: u64) -> String {
+        let commands = self.moves + self.turns;
+        let efficiency = match commands {
+            0 => 100.0,
+            _ => self.distance as f64 * 100.0 / commands as f64,
+        };
+        format!("{} messages picked up with {} commands ({} moves, {} turns, {} blocked), {} at least, {:.1}% efficiency",
+            completed, commands, self.moves, self.turns, self.blocked, self.distance, efficiency)
+    }
+}
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The label a failed session is counted under
+pub fn error_label(error: &BError) -> &'static str {
+    match error {
+        BError::Io(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => "Timeout",
+        _ => error.name(),
+    }
+}
+
+/// A connection or login was refused before the state machine ran, `reason` is rate, ip or username
+pub fn blocked(reason: &'static str) {
+    *registry().blocked.entry(reason).or_default() += 1;
+}
+
+/// An IP address or a username got locked out
+pub fn locked_out() {
+    registry().lockouts += 1;
+}
+
+/// Collects the metrics of one session, created when the session is accepted
+pub struct SessionMetrics {
+    moves: u64,
+    recharge_started: Option<Instant>,
+}
+
+impl SessionMetrics {
+
+    pub fn new() -> SessionMetrics {
+        registry().accepted += 1;
+        SessionMetrics { moves: 0, recharge_started: None }
+    }
+
+    pub fn client(&mut self, message: &ClientMessage) {
+        registry().message_size.observe((message.0.len() + 2) as f64);
+    }
+
+    pub fn server(&mut self, message: &ServerMessage) {
+        match message {
+            ServerMessage::Move => self.moves += 1,
+            ServerMessage::LoginFailed
+                | ServerMessage::SyntaxError
+                | ServerMessage::LogicError
+                | ServerMessage::KeyOutOfRangeError =>
+                *registry().error_responses.entry(message.body()).or_default() += 1,
+            _ => {}
+        }
+    }
+
+    pub fn timeout(&mut self, timeout: &BTimeout) {
+        match timeout {
+            BTimeout::Refilling => {
+                self.recharge_started = Some(Instant::now());
+                registry().recharges += 1;
+            }
+            BTimeout::Normal => {
+                if let Some(started) = self.recharge_started.take() {
+                    registry().recharge_duration.observe(started.elapsed().as_secs_f64());
+                }
+            }
+        }
+    }
+
+    pub fn finished(&mut self, stats: &PathStats) {
+        registry().finished(self.moves, stats);
+    }
+
+    /// `login_key` is the key index if the session failed while validating the login
+    pub fn failed(&mut self, label: &'static str, login_key: Option<usize>) {
+        registry().failed(label, login_key);
+    }
+}
+
+impl Default for SessionMetrics {
+    fn default() -> Self {
+        SessionMetrics::new()
+    }
+}
+
+/// All the metrics in the Prometheus text format
+pub fn render() -> String {
+    registry().render()
+}
+
+/// One line summary of the path efficiency over all the picked up messages
+pub fn path_report() -> String {
+    let registry = registry();
+    registry.path.report(registry.completed)
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value).unwrap();
+}
+
+fn labeled<I: Iterator<Item = (String, u64)>>(out: &mut String, name: &str, help: &str, label: &str, values: I) {
+    writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name).unwrap();
+    for (value, count) in values {
+        writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value.replace('\\', "\\\\").replace('"', "\\\""), count).unwrap();
+    }
+}
+
+/// Binds the metrics HTTP listener and serves `GET /metrics` from a background thread
+pub fn serve(addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Metrics available on http://{}/metrics", addr);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let res = stream.and_then(handle_http);
+            if let Err(e) = res {
+                debug!("Metrics request failed: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_http(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut bytes = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+        let bytes_num = stream.read(&mut bytes)?;
+        if bytes_num == 0 {
+            break;
+        }
+        request.extend_from_slice(&bytes[..bytes_num]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        _ => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(moves: u32, turns: u32, blocked: u32, distance: u32) -> PathStats {
+        let mut stats = PathStats::new();
+        (stats.moves, stats.turns, stats.blocked, stats.distance) = (moves, turns, blocked, Some(distance));
+        stats
+    }
+
+    /// The lines of the metric in the rendered registry, without the comments
+    fn lines(out: &str, name: &str) -> Vec<String> {
+        out.lines().filter(|line| line.starts_with(name)).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn recorded_sessions_are_rendered() {
+        let mut registry = Registry::new();
+        registry.accepted += 3;
+        registry.finished(12, &stats(12, 3, 1, 10));
+        registry.failed("Timeout", None);
+        registry.failed("HashMismatch", Some(1));
+        *registry.error_responses.entry(ServerMessage::LoginFailed.body()).or_default() += 1;
+        for size in [4.0, 9.0, 30.0] {
+            registry.message_size.observe(size);
+        }
+        let out = registry.render();
+
+        assert!(out.contains("# HELP bobika_sessions_accepted_total Accepted connections\n# TYPE bobika_sessions_accepted_total counter\n"));
+        assert_eq!(lines(&out, "bobika_sessions_accepted_total"), ["bobika_sessions_accepted_total 3"]);
+        assert_eq!(lines(&out, "bobika_sessions_failed_total"), [
+            "bobika_sessions_failed_total{error=\"HashMismatch\"} 1",
+            "bobika_sessions_failed_total{error=\"Timeout\"} 1",
+        ]);
+        assert_eq!(lines(&out, "bobika_login_failures_total"), ["bobika_login_failures_total{key=\"1\"} 1"]);
+        assert_eq!(lines(&out, "bobika_error_responses_total"), ["bobika_error_responses_total{response=\"300 LOGIN FAILED\"} 1"]);
+        assert_eq!(lines(&out, "bobika_path_blocked_moves_total"), ["bobika_path_blocked_moves_total 1"]);
+
+        // The buckets are cumulative and end with +Inf
+        assert_eq!(lines(&out, "bobika_moves_per_extraction"), [
+            "bobika_moves_per_extraction_bucket{le=\"5\"} 0",
+            "bobika_moves_per_extraction_bucket{le=\"10\"} 0",
+            "bobika_moves_per_extraction_bucket{le=\"20\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"50\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"100\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"200\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"500\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"1000\"} 1",
+            "bobika_moves_per_extraction_bucket{le=\"+Inf\"} 1",
+            "bobika_moves_per_extraction_sum 12",
+            "bobika_moves_per_extraction_count 1",
+        ]);
+        let sizes = lines(&out, "bobika_message_size_bytes");
+        assert_eq!(&sizes[..3], [
+            "bobika_message_size_bytes_bucket{le=\"4\"} 1",
+            "bobika_message_size_bytes_bucket{le=\"8\"} 1",
+            "bobika_message_size_bytes_bucket{le=\"12\"} 2",
+        ]);
+        assert_eq!(&sizes[6..], [
+            "bobika_message_size_bytes_bucket{le=\"+Inf\"} 3",
+            "bobika_message_size_bytes_sum 43",
+            "bobika_message_size_bytes_count 3",
+        ]);
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut out = String::new();
+        labeled(&mut out, "test_total", "Test", "value", [("a \"b\" \\c".to_string(), 2)].into_iter());
+        assert_eq!(out.lines().last(), Some("test_total{value=\"a \\\"b\\\" \\\\c\"} 2"));
+    }
+
+    #[test]
+    fn timeouts_get_their_own_label() {
+        let timeout = BError::Io(io::Error::from(io::ErrorKind::WouldBlock));
+        assert_eq!(error_label(&timeout), "Timeout");
+        assert_eq!(error_label(&BError::Io(io::Error::from(io::ErrorKind::TimedOut))), "Timeout");
+        assert_eq!(error_label(&BError::Io(io::Error::from(io::ErrorKind::BrokenPipe))), "Io");
+        assert_eq!(error_label(&BError::AnswerMismatch), "AnswerMismatch");
+    }
+
+    #[test]
+    fn path_report_sums_the_sessions() {
+        let mut totals = PathTotals::default();
+        assert_eq!(totals.report(0),
+            "0 messages picked up with 0 commands (0 moves, 0 turns, 0 blocked), 0 at least, 100.0% efficiency");
+
+        totals.add(&stats(10, 4, 2, 8));
+        totals.add(&stats(5, 1, 0, 4));
+        assert_eq!(totals.report(2),
+            "2 messages picked up with 20 commands (15 moves, 5 turns, 2 blocked), 12 at least, 60.0% efficiency");
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+
+use crate::stats::PathStats;
+
+/// The waypoints a robot picks up messages at, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mission {
+    pub waypoints: Vec<(i32, i32)>,
+}
+
+impl Mission {
+
+    pub fn single(target: (i32, i32)) -> Mission {
+        Mission { waypoints: vec![target] }
+    }
+}
+
+/// How far a logged in robot got with its mission
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Waypoints after the one the robot is heading to
+    pub remaining: VecDeque<(i32, i32)>,
+    /// Messages picked up so far
+    pub messages: Vec<String>,
+    pub stats: PathStats,
+}
+
+impl Progress {
+
+    /// Starts the mission, returns the first waypoint and the progress after it
+    pub fn start(mission: &Mission) -> ((i32, i32), Progress) {
+        let mut remaining: VecDeque<(i32, i32)> = mission.waypoints.iter().copied().collect();
+        let first = remaining.pop_front().unwrap_or((0, 0));
+        (first, Progress { remaining, ..Progress::default() })
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::axis::AxisStrategy;
+use crate::errors::BError;
+use crate::messages::ServerMessage;
+use crate::path::Orient;
+use crate::planner::Planner;
+
+/// What the robot should do next
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Send(ServerMessage),
+    Arrived,
+}
+
+/// Drives the robot to the target once its position and orientation are known.
+///
+/// `next` is called with the first known position right after the strategy is created
+/// and then with every position the robot reports after a command.
+pub trait NavigationStrategy: fmt::Debug + Send {
+    fn next(&mut self, position: (i32, i32)) -> Result<Step, BError>;
+
+    /// Where the robot faces after the commands sent so far
+    fn orient(&self) -> Orient;
+
+    /// Obstacles found since the last call, to be shared with other sessions
+    fn discovered(&mut self) -> Vec<(i32, i32)> {
+        Vec::new()
+    }
+}
+
+/// The navigation strategies the server can be configured with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Shortest path over the known obstacles, see [`Planner`]
+    Planner,
+    /// Greedy moves towards an axis and following it, see [`AxisStrategy`]
+    Axis,
+}
+
+impl Strategy {
+
+    pub fn parse(value: &str) -> Option<Strategy> {
+        match value {
+            "planner" => Some(Self::Planner),
+            "axis" => Some(Self::Axis),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Planner => "planner",
+            Self::Axis => "axis",
+        }
+    }
+
+    /// `known` are the obstacles found on the map by other sessions
+    pub fn create(&self, position: (i32, i32), orient: Orient, target: (i32, i32), known: HashSet<(i32, i32)>) -> Box<dyn NavigationStrategy> {
+        match self {
+            Self::Planner => Box::new(Planner::new(position, orient, target, known)),
+            Self::Axis => Box::new(AxisStrategy::new(position, orient, target, known)),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::ServerConfig;
+use crate::constants::BTimeout;
+use crate::errors::BError;
+use std::net::SocketAddr;
+
+use crate::lockout::{self, Subject};
+use crate::logger::{self, LogContext, LogLevel};
+use crate::messages::{ClientMessage, ServerMessage};
+use crate::metrics::{self, SessionMetrics};
+use crate::stats::PathStats;
+use crate::transcript::Recorder;
+use crate::visualize::Track;
+
+/// Watches a session from its driver, feeds the transcript and the metrics
+/// and logs the explored grid when the session ends
+pub struct Observer<'a> {
+    config: &'a ServerConfig,
+    peer: Option<SocketAddr>,
+    recorder: Recorder,
+    metrics: SessionMetrics,
+    track: Track,
+}
+
+impl Observer<'_> {
+
+    pub fn new<'a>(config: &'a ServerConfig, context: &LogContext) -> Observer<'a> {
+        Observer {
+            config,
+            peer: context.peer,
+            recorder: Recorder::for_session(config, context),
+            metrics: SessionMetrics::new(),
+            track: Track::new(),
+        }
+    }
+
+    pub fn client(&mut self, message: &ClientMessage) {
+        self.recorder.client(message);
+        self.metrics.client(message);
+        self.track.client(&message.0);
+    }
+
+    pub fn server(&mut self, message: &ServerMessage) {
+        // 200 OK is only sent when the login succeeds
+        if *message == ServerMessage::NoProblemo {
+            for subject in self.subjects() {
+                lockout::shared().succeeded(&subject);
+            }
+        }
+        self.recorder.server(message);
+        self.metrics.server(message);
+        self.track.server(&message.body());
+    }
+
+    pub fn timeout(&mut self, timeout: &BTimeout) {
+        self.metrics.timeout(timeout);
+    }
+
+    /// The message was picked up
+    pub fn finished(&mut self, stats: &PathStats) {
+        self.metrics.finished(stats);
+        self.log_map();
+    }
+
+    /// The session failed, `transport` errors did not come from the state machine
+    pub fn failed(&mut self, error: &BError, transport: bool, login_key: Option<usize>) {
+        if transport {
+            self.recorder.closed(&error.to_string());
+        }
+        self.metrics.failed(metrics::error_label(error), login_key);
+        if !transport && error.server_response() == ServerMessage::LoginFailed {
+            self.login_failed();
+        }
+        self.log_map();
+    }
+
+    /// The peer or username is locked out, the session is closed without a response
+    pub fn locked_out(&mut self) {
+        self.recorder.closed("locked out");
+        self.metrics.failed("LockedOut", None);
+    }
+
+    fn login_failed(&self) {
+        for subject in self.subjects() {
+            if let Some(lockout) = lockout::shared().failed(subject.clone(), self.config.lockout_after, self.config.lockout) {
+                warn!("Locking out {:?} for {:.1}s", subject, lockout.as_secs_f64());
+                metrics::locked_out();
+            }
+        }
+    }
+
+    /// The IP address and the username the session logs in from
+    fn subjects(&self) -> Vec<Subject> {
+        let ip = self.peer.map(|peer| Subject::Ip(peer.ip()));
+        let username = self.track.username.clone().map(Subject::Username);
+        ip.into_iter().chain(username).collect()
+    }
+
+    pub fn shutdown(&mut self) {
+        self.recorder.closed("shutdown");
+        self.metrics.failed("Shutdown", None);
+        self.log_map();
+    }
+
+    fn log_map(&self) {
+        if !logger::enabled(LogLevel::Debug) || self.track.path.is_empty() {
+            return;
+        }
+        let targets = match &self.track.username {
+            Some(username) => self.config.mission_for(username).waypoints,
+            None => Vec::new(),
+        };
+        debug!("Map:\n{}", self.track.render(&targets).trim_end());
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::thread;
+
+use crate::constants::MAP_SAVE_INTERVAL;
+
+/// Obstacles found by robots, shared by the sessions working on the same map
+pub struct ObstacleStore {
+    maps: Mutex<BTreeMap<String, HashSet<(i32, i32)>>>,
+    /// Something was recorded since the last save
+    changed: AtomicBool,
+    /// Held while a file is written, so two saves do not share the temporary file
+    saving: Mutex<()>,
+}
+
+impl ObstacleStore {
+
+    pub fn new() -> ObstacleStore {
+        ObstacleStore { maps: Mutex::new(BTreeMap::new()), changed: AtomicBool::new(false), saving: Mutex::new(()) }
+    }
+
+    fn maps(&self) -> MutexGuard<'_, BTreeMap<String, HashSet<(i32, i32)>>> {
+        self.maps.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// All the obstacles known on the map
+    pub fn known(&self, map: &str) -> HashSet<(i32, i32)> {
+        self.maps().get(map).cloned().unwrap_or_default()
+    }
+
+    pub fn record(&self, map: &str, cells: &[(i32, i32)]) {
+        if cells.is_empty() {
+            return;
+        }
+        debug!("+ Map: Recording {:?} on {}", cells, map);
+        let mut maps = self.maps();
+        let known = maps.entry(map.to_string()).or_default();
+        for cell in cells {
+            if known.insert(*cell) {
+                self.changed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Adds the obstacles from a file written by `save`, a missing file is an empty store
+    pub fn load(&self, path: &str) -> io::Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut maps = self.maps();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || io::Error::new(ErrorKind::InvalidData, format!("line {}: \"{}\"", index + 1, line));
+
+            let mut parts = line.split_whitespace();
+            let map = parts.next().ok_or_else(invalid)?;
+            let x = parts.next().and_then(|x| x.parse().ok()).ok_or_else(invalid)?;
+            let y = parts.next().and_then(|y| y.parse().ok()).ok_or_else(invalid)?;
+            if parts.next().is_some() {
+                return Err(invalid());
+            }
+            maps.entry(map.to_string()).or_default().insert((x, y));
+        }
+        Ok(())
+    }
+
+    /// Writes one `<map> <x> <y>` line per obstacle,
+    /// the sessions can record new ones while the file is being written
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let _saving = self.saving.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let maps = {
+            let maps = self.maps();
+            self.changed.store(false, Ordering::SeqCst);
+            maps.clone()
+        };
+
+        let mut out = String::from("# map x y\n");
+        for (map, cells) in maps.iter() {
+            let mut cells: Vec<_> = cells.iter().collect();
+            cells.sort();
+            for (x, y) in cells {
+                writeln!(out, "{} {} {}", map, x, y).unwrap();
+            }
+        }
+
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Saves the store if anything was recorded since the last save, returns whether it did
+    pub fn save_changes(&self, path: &str) -> io::Result<bool> {
+        if !self.changed.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        self.save(path)?;
+        Ok(true)
+    }
+}
+
+impl Default for ObstacleStore {
+    fn default() -> Self {
+        ObstacleStore::new()
+    }
+}
+
+/// The store used by the server
+pub fn shared() -> &'static ObstacleStore {
+    static STORE: OnceLock<ObstacleStore> = OnceLock::new();
+    STORE.get_or_init(ObstacleStore::new)
+}
+
+/// Saves the shared store to the file every `MAP_SAVE_INTERVAL` if it changed,
+/// so the sessions never wait for the disk. Runs on its own thread for as long as the server does.
+pub fn autosave(path: String) {
+    thread::spawn(move || loop {
+        thread::sleep(MAP_SAVE_INTERVAL);
+        match shared().save_changes(&path) {
+            Ok(true) => debug!("Obstacles saved to {}", path),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to save the obstacles to {}: {}", path, e),
+        }
+    });
+}
@@ -1,13 +1,22 @@
-use crate::{messages::{ClientMessage, ServerMessage}, state_machine::{BState, PRes}, errors::BError};
+use std::collections::HashSet;
 
-#[derive(Debug)]
-#[derive(PartialEq)]
+use crate::{messages::{ClientMessage, ServerMessage}, state_machine::{BState, PRes}, errors::{BError, Unreachable}};
+use crate::config::ServerConfig;
+use crate::navigation::{NavigationStrategy, Step};
+use crate::mission::Progress;
+use crate::obstacles;
+
+#[derive(Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Orient {
     NORTH, SOUTH, EAST, WEST,
 }
 
 impl Orient {
-    fn left(&self) -> Orient {
+    pub const ALL: [Orient; 4] = [Orient::NORTH, Orient::SOUTH, Orient::EAST, Orient::WEST];
+
+    pub fn left(&self) -> Orient {
         match self {
             Self::NORTH => Self::WEST,
             Self::SOUTH =>Self::EAST,
@@ -15,244 +24,202 @@ impl Orient {
             Self::WEST =>Self::SOUTH,
         }
     }
-    fn right(&self) -> Orient {
-        self.left().left().left()
+    pub fn right(&self) -> Orient {
+        match self {
+            Self::NORTH => Self::EAST,
+            Self::SOUTH => Self::WEST,
+            Self::EAST => Self::SOUTH,
+            Self::WEST => Self::NORTH,
+        }
     }
-    fn is_valid_for(&self, x: i32, y: i32) -> bool {
-        false
-            || (x > 0 && self == &Orient::WEST)
-            || (x < 0 && self == &Orient::EAST)
-            || (y > 0 && self == &Orient::SOUTH)
-            || (y < 0 && self == &Orient::NORTH)
+    /// The shortest sequence of turns to face `target`, never more than two
+    pub fn rotate_to(&self, target: Orient) -> Vec<ServerMessage> {
+        if *self == target {
+            vec![]
+        } else if self.left() == target {
+            vec![ServerMessage::Left]
+        } else if self.right() == target {
+            vec![ServerMessage::Right]
+        } else {
+            vec![ServerMessage::Left, ServerMessage::Left]
+        }
     }
-    fn move_in(&self, (x, y): (i32, i32)) -> (i32, i32) {
+    /// Moving in this orientation gets the robot at `(x, y)` closer to the target
+    pub fn is_valid_for(&self, (x, y): (i32, i32), (tx, ty): (i32, i32)) -> bool {
+        (x > tx && self == &Orient::WEST)
+            || (x < tx && self == &Orient::EAST)
+            || (y > ty && self == &Orient::SOUTH)
+            || (y < ty && self == &Orient::NORTH)
+    }
+    pub fn move_in(&self, (x, y): (i32, i32)) -> (i32, i32) {
         match self {
-            Self::NORTH => (x + 0, y + 1),
-            Self::SOUTH => (x + 0, y - 1),
-            Self::EAST  => (x + 1, y + 0),
-            Self::WEST  => (x - 1, y + 0),
+            Self::NORTH => (x, y + 1),
+            Self::SOUTH => (x, y - 1),
+            Self::EAST  => (x + 1, y),
+            Self::WEST  => (x - 1, y),
         }
     }
 }
 
+/// Turns made after blocked probing moves before giving up,
+/// with three all the directions have been tried
+const MAX_PROBE_TURNS: u32 = 3;
+
 #[derive(Debug)]
 pub enum PathState {
-    FindingPosition,
-    FindingOrientation((i32, i32)),
-    FindPath((i32, i32), Orient),
-    SetupAxis((i32, i32), Orient),
-    FollowAxis((i32, i32), Orient),
-
-    DoLeft(Box<PathState>),
-    DoRight(Box<PathState>),
-    DoMove(Box<PathState>),
+    /// The first move was sent, its reply tells the position
+    FindingPosition { target: (i32, i32) },
+    /// A move was sent from `from`, the change of the position tells the orientation
+    FindingOrientation { from: (i32, i32), turns: u32, target: (i32, i32) },
+    /// Turned after a blocked move, the next move probes another direction
+    Turned { turns: u32, target: (i32, i32) },
+    Navigate(Box<dyn NavigationStrategy>),
 }
 
 impl PathState {
-    pub fn handle_message(self, message: ClientMessage) -> Result<(BState, PRes), BError> {
+    pub fn handle_message(self, message: ClientMessage, config: &ServerConfig, mut progress: Progress) -> Result<(BState, PRes), BError> {
         let (x, y) = parse_message(&message.0)?;
+        progress.stats.report((x, y));
 
-        println!("+ Path: State {:?}", self);
+        debug!("+ Path: State {:?}", self);
 
         match self {
-            Self::FindingPosition => {
+            Self::FindingPosition { target } => {
+                progress.stats.start((x, y), target);
+                if (x, y) == target {
+                    return Ok(extract(progress, (x, y), None));
+                }
 
-                println!("+ Path: Processing first rotation");
-                println!("+ Path: Trying the first move");
+                debug!("+ Path: Trying the second move");
 
-                let next_state = Self::FindingOrientation((x, y));
+                let next_state = Self::FindingOrientation { from: (x, y), turns: 0, target };
                 let message = ServerMessage::Move;
 
-                Ok((wp(next_state), wm(message)))
+                Ok(send(next_state, progress, message))
             },
 
-            Self::FindingOrientation((px, py)) => {
-
-                if px == x && py == y {
-
-                    println!("+ Path: First move blocked");
-                    println!("+ Path: Trying again");
+            Self::Turned { turns, target } => {
 
-                    let next_state = Self::FindingPosition;
-                    let message = ServerMessage::Right;
+                debug!("+ Path: Trying the move again");
 
-                    Ok((wp(next_state), wm(message)))
-                } else {
+                let next_state = Self::FindingOrientation { from: (x, y), turns, target };
+                let message = ServerMessage::Move;
 
-                    println!("+ Path: First move succeed");
-
-                    let orient = match x - px {
-                        1 => Orient::EAST,
-                        -1 => Orient::WEST,
-                        0 => if y - py == 1 {
-                            Orient::NORTH
-                        } else {
-                            Orient::SOUTH
-                        },
-                        _ => panic!("Wtf just happened"),
-                    };
-                    println!("+ Path: Orientation is {:?}", orient);
-
-                    match (x, y) {
-                        (0, 0) => {
-                            println!("+ Path: Ready to extract");
-
-                            let next_state = BState::Extract;
-                            let message = ServerMessage::PickUp;
-                            Ok((next_state, wm(message)))
-                        },
-                        (0, _) | (_, 0) => {
-                            println!("+ Path: Ready to follow axis");
-
-                            let next_state = Self::SetupAxis((x, y), orient.left());
-                            let message = ServerMessage::Left;
-                            Ok((wp(next_state), wm(message)))
-                        },
-                        _ => {
-                            let is_valid = orient.is_valid_for(x, y);
-
-                            if is_valid {
-                                println!("+ Path: Orientation is valid");
-
-                                let next_state = Self::FindPath((x, y), orient);
-                                let message = ServerMessage::Move;
-                                Ok((wp(next_state), wm(message)))
-                            } else{
-                                println!("+ Path: Rotating by π");
-
-                                let next_state = Self::FindPath((x, y), orient.left().left());
-                                let message = ServerMessage::Left;
-                                let wrapper = Self::DoLeft(Box::new(Self::DoMove(Box::new(next_state))));
-                                Ok((wp(wrapper), wm(message)))
-                            }
-                        }
-                    }
-                }
+                Ok(send(next_state, progress, message))
             },
 
-            Self::FindPath((px, py), orient) => {
-                if x == 0 || y == 0 {
-                    println!("+ Path: Moving to axis");
-
-                    let next_state = Self::SetupAxis((x, y), orient.left());
-                    let message = ServerMessage::Left;
-                    Ok((wp(next_state), wm(message)))
-                } else if px == x && py == y {
-                    if orient.left().is_valid_for(x, y) {
-
-                        println!("+ Path: Obsticke hit! Rotating left");
+            Self::FindingOrientation { from: (px, py), turns, target } => {
 
-                        let next_state = Self::DoMove(Box::new(Self::FindPath((x, y), orient.left())));
-                        let message = ServerMessage::Left;
-                        Ok((wp(next_state), wm(message)))
-                    } else {
+                if px == x && py == y {
 
-                        println!("+ Path: Obsticke hit! Rotating right");
+                    debug!("+ Path: Move blocked");
 
-                        let next_state = Self::DoMove(Box::new(Self::FindPath((x, y), orient.right())));
-                        let message = ServerMessage::Right;
-                        Ok((wp(next_state), wm(message)))
+                    if turns >= MAX_PROBE_TURNS {
+                        share(config, &Orient::ALL.map(|orient| orient.move_in((x, y))));
+                        return Err(BError::Enclosed((x, y)));
                     }
-                } else {
 
-                    println!("+ Path: No problemo, let's move again");
-
-                    let next_state = Self::FindPath((x, y), orient);
-                    let message = ServerMessage::Move;
-                    Ok((wp(next_state), wm(message)))
-                }
-            }
-
-            Self::SetupAxis((_, _), orient) => {
-                if x == 0 && y == 0 {
-
-                    println!("+ Path: Extracting");
+                    let next_state = Self::Turned { turns: turns + 1, target };
+                    let message = ServerMessage::Right;
 
-                    let next_state = BState::Extract;
-                    let message = ServerMessage::PickUp;
-                    Ok((next_state, wm(message)))
+                    Ok(send(next_state, progress, message))
                 } else {
-                    if orient.is_valid_for(x, y) {
-                        println!("+ Path: Got the right direction, moving");
-
-                        let next_state = Self::FollowAxis((x, y), orient);
-                        let message = ServerMessage::Move;
-                        Ok((wp(next_state), wm(message)))
-                    } else {
 
-                        println!("+ Path: Wrong direction, moving");
+                    debug!("+ Path: Move succeed");
 
-                        let next_state = Self::SetupAxis((x, y), orient.left());
-                        let message = ServerMessage::Left;
-                        Ok((wp(next_state), wm(message)))
-                    }
+                    let orient = match (x - px, y - py) {
+                        (1, 0) => Orient::EAST,
+                        (-1, 0) => Orient::WEST,
+                        (0, 1) => Orient::NORTH,
+                        (0, -1) => Orient::SOUTH,
+                        _ => return Err(BError::ImpossibleMove { from: (px, py), to: (x, y) }),
+                    };
+                    debug!("+ Path: Orientation is {:?}", orient);
+
+                    // Every blocked probe faced left of the direction after the next right turn
+                    let probed: Vec<(i32, i32)> = (0..turns)
+                        .scan(orient, |facing, _| {
+                            *facing = facing.left();
+                            Some(facing.move_in((px, py)))
+                        })
+                        .collect();
+                    share(config, &probed);
+
+                    let mut known = known_obstacles(config);
+                    known.extend(probed);
+                    let strategy = config.strategy.create((x, y), orient, target, known);
+                    navigate(strategy, progress, (x, y), config)
                 }
-            }
-
-            Self::FollowAxis((px, py), orient) => {
-                if x == 0 && y == 0 {
-                    println!("+ Path: Extracting");
-
-                    let next_state = BState::Extract;
-                    let message = ServerMessage::PickUp;
-                    Ok((next_state, wm(message)))
-                } else if px == x && py == y {
-                    println!("+ Path: Obsticle hit, going around");
-
-                    let coord = orient.move_in((x, y));
-                    let next_state = Self::FollowAxis(coord, orient);
-                    let wrapped = do_m(do_r(do_m(do_m(do_r(do_m(do_l(next_state)))))));
-                    let message = ServerMessage::Left;
-                    Ok((wp(wrapped), wm(message)))
-                } else {
-                    println!("+ Path: No problemo, lets move");
+            },
 
-                    let next_state = Self::FollowAxis((x, y), orient);
-                    let message = ServerMessage::Move;
-                    Ok((wp(next_state), wm(message)))
-                }
-            }
-
-            Self::DoLeft(next_state) => {
-                let message = ServerMessage::Left;
-                Ok((wp(*next_state), wm(message)))
-            }
-            Self::DoRight(next_state) => {
-                let message = ServerMessage::Right;
-                Ok((wp(*next_state), wm(message)))
-            }
-            Self::DoMove(next_state) => {
-                let message = ServerMessage::Move;
-                Ok((wp(*next_state), wm(message)))
-            }
+            Self::Navigate(strategy) => navigate(strategy, progress, (x, y), config),
         }
     }
 }
 
-fn do_m(state: PathState) -> PathState {
-    PathState::DoMove(Box::new(state))
+/// Heads for the next waypoint from the one the robot picked up a message at,
+/// the orientation is not known if the robot started at the waypoint
+pub fn next_waypoint(position: (i32, i32), orient: Option<Orient>, target: (i32, i32), mut progress: Progress, config: &ServerConfig) -> Result<(BState, PRes), BError> {
+    debug!("+ Path: Heading to {:?}", target);
+    progress.stats.start(position, target);
+
+    match orient {
+        Some(orient) => {
+            let strategy = config.strategy.create(position, orient, target, known_obstacles(config));
+            navigate(strategy, progress, position, config)
+        },
+        None if position == target => Ok(extract(progress, position, None)),
+        None => {
+            let next_state = PathState::FindingOrientation { from: position, turns: 0, target };
+            Ok(send(next_state, progress, ServerMessage::Move))
+        },
+    }
+}
+
+fn navigate(mut strategy: Box<dyn NavigationStrategy>, progress: Progress, position: (i32, i32), config: &ServerConfig) -> Result<(BState, PRes), BError> {
+    let step = strategy.next(position);
+    share(config, &strategy.discovered());
+
+    match step? {
+        Step::Arrived => Ok(extract(progress, position, Some(strategy.orient()))),
+        Step::Send(_) if progress.stats.commands() >= config.step_budget => {
+            Err(BError::Unreachable(Unreachable::StepBudget(config.step_budget)))
+        },
+        Step::Send(message) => Ok(send(PathState::Navigate(strategy), progress, message)),
+    }
 }
 
-fn do_l(state: PathState) -> PathState {
-    PathState::DoLeft(Box::new(state))
+/// Adds the obstacles to the shared map, `obstacles::autosave` writes them to the map file
+fn share(config: &ServerConfig, cells: &[(i32, i32)]) {
+    if let Some(map) = &config.map {
+        obstacles::shared().record(map, cells);
+    }
 }
 
-fn do_r(state: PathState) -> PathState {
-    PathState::DoRight(Box::new(state))
+fn known_obstacles(config: &ServerConfig) -> HashSet<(i32, i32)> {
+    match &config.map {
+        Some(map) => obstacles::shared().known(map),
+        None => HashSet::new(),
+    }
 }
 
-fn wp(state: PathState) -> BState {
-    BState::FindPath(state)
+fn extract(progress: Progress, position: (i32, i32), orient: Option<Orient>) -> (BState, PRes) {
+    debug!("+ Path: Extracting");
+
+    let next_state = BState::Extract { progress, position, orient };
+    let message = ServerMessage::PickUp;
+    (next_state, PRes::SendMessage(message))
 }
-fn wm(msg: ServerMessage) -> PRes {
-    PRes::SendMessage(msg)
+
+fn send(state: PathState, mut progress: Progress, msg: ServerMessage) -> (BState, PRes) {
+    progress.stats.command(&msg);
+    (BState::FindPath(state, progress), PRes::SendMessage(msg))
 }
 
 fn parse_message(str: &str) -> Result<(i32, i32), BError> {
-    if &str[..3] == "OK " {
-        parse_xy(&str[3..])
-    } else {
-        Err(BError::FailedToParseNumber(None))
+    match str.strip_prefix("OK ") {
+        Some(xy) => parse_xy(xy),
+        None => Err(BError::FailedToParseNumber(None)),
     }
 }
 
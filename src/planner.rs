@@ -0,0 +1,248 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use crate::errors::{BError, Unreachable};
+use crate::messages::ServerMessage;
+use crate::navigation::{NavigationStrategy, Step};
+use crate::path::Orient;
+
+/// Cells around the known world the search may use to get around obstacles.
+/// One is enough, the ring around all the known obstacles is always free.
+const SEARCH_MARGIN: i32 = 1;
+
+/// Plans the shortest command sequence to the target over the known map.
+///
+/// Unknown cells are expected to be free, every move that does not change
+/// the position marks the cell in front of the robot as blocked and the path
+/// is planned again. Moves and turns cost the same, one command each.
+/// Obstacles other robots found on the same map are avoided as well,
+/// but only the ones this robot found can make the target unreachable.
+pub struct Planner {
+    position: (i32, i32),
+    orient: Orient,
+    target: (i32, i32),
+    obstacles: HashSet<(i32, i32)>,
+    /// Obstacles from the shared map, not confirmed by this robot
+    hints: HashSet<(i32, i32)>,
+    /// Obstacles found since the last `discovered` call
+    discovered: Vec<(i32, i32)>,
+    plan: VecDeque<ServerMessage>,
+    last: Option<ServerMessage>,
+}
+
+impl fmt::Debug for Planner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Planner({:?}, {:?}, {} obstacles, {} hints, {} planned)",
+            self.position, self.orient, self.obstacles.len(), self.hints.len(), self.plan.len())
+    }
+}
+
+impl Planner {
+
+    /// `hints` are obstacles others found, planned around until they block every path
+    pub fn new(position: (i32, i32), orient: Orient, target: (i32, i32), hints: HashSet<(i32, i32)>) -> Planner {
+        Planner {
+            position,
+            orient,
+            target,
+            obstacles: HashSet::new(),
+            hints,
+            discovered: Vec::new(),
+            plan: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    pub fn obstacles(&self) -> &HashSet<(i32, i32)> {
+        &self.obstacles
+    }
+
+    fn update(&mut self, reported: (i32, i32)) -> Result<(), BError> {
+        let expected = match self.last {
+            Some(ServerMessage::Move) => self.orient.move_in(self.position),
+            _ => self.position,
+        };
+
+        if reported == expected {
+            self.position = reported;
+            self.hints.remove(&reported);
+        } else if reported == self.position && self.last == Some(ServerMessage::Move) {
+            debug!("+ Path: Obstacle found at {:?}", expected);
+            if self.obstacles.insert(expected) && !self.hints.remove(&expected) {
+                self.discovered.push(expected);
+            }
+            self.plan.clear();
+        } else if self.last.is_some() {
+            return Err(BError::ImpossibleMove { from: self.position, to: reported });
+        }
+        Ok(())
+    }
+
+    fn replan(&mut self) -> Result<(), BError> {
+        let known = self.obstacles.union(&self.hints).copied().collect();
+        let plan = match search(self.position, self.orient, self.target, &known) {
+            Some(plan) => plan,
+            None if !self.hints.is_empty() => {
+                debug!("+ Path: No path around the shared obstacles, ignoring them");
+                self.hints.clear();
+                return self.replan();
+            },
+            None => return Err(BError::Unreachable(Unreachable::NoPath { target: self.target, obstacles: self.obstacles.len() })),
+        };
+
+        debug!("+ Path: Planned {} commands", plan.len());
+        self.plan = plan;
+        Ok(())
+    }
+}
+
+impl NavigationStrategy for Planner {
+
+    fn next(&mut self, reported: (i32, i32)) -> Result<Step, BError> {
+        self.update(reported)?;
+
+        if self.position == self.target {
+            return Ok(Step::Arrived);
+        }
+
+        if self.plan.is_empty() {
+            self.replan()?;
+        }
+
+        let command = self.plan.pop_front()
+            .expect("A plan to a different cell is never empty");
+        match command {
+            ServerMessage::Left => self.orient = self.orient.left(),
+            ServerMessage::Right => self.orient = self.orient.right(),
+            _ => {}
+        }
+        self.last = Some(command.clone());
+        Ok(Step::Send(command))
+    }
+
+    fn orient(&self) -> Orient {
+        self.orient
+    }
+
+    fn discovered(&mut self) -> Vec<(i32, i32)> {
+        std::mem::take(&mut self.discovered)
+    }
+}
+
+type Node = ((i32, i32), Orient);
+
+/// A* over (cell, orientation), returns the commands to reach the target.
+/// Every step turns the shortest way to one of the neighbours and moves there.
+pub fn search(
+    start: (i32, i32), orient: Orient, target: (i32, i32), obstacles: &HashSet<(i32, i32)>,
+) -> Option<VecDeque<ServerMessage>> {
+    let cells = obstacles.iter().chain([&start, &target]);
+    let min_x = cells.clone().map(|c| c.0).min()? - SEARCH_MARGIN;
+    let max_x = cells.clone().map(|c| c.0).max()? + SEARCH_MARGIN;
+    let min_y = cells.clone().map(|c| c.1).min()? - SEARCH_MARGIN;
+    let max_y = cells.map(|c| c.1).max()? + SEARCH_MARGIN;
+    let inside = |(x, y): (i32, i32)| min_x <= x && x <= max_x && min_y <= y && y <= max_y;
+
+    let heuristic = |(x, y): (i32, i32)| (x - target.0).unsigned_abs() + (y - target.1).unsigned_abs();
+
+    let mut costs: HashMap<Node, u32> = HashMap::new();
+    let mut came_from: HashMap<Node, (Node, Vec<ServerMessage>)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+
+    costs.insert((start, orient), 0);
+    open.push(Reverse((heuristic(start), 0u32, start, orient)));
+
+    while let Some(Reverse((_, cost, position, orient))) = open.pop() {
+        if position == target {
+            return Some(unwind(&came_from, (position, orient)));
+        }
+        if costs.get(&(position, orient)).is_some_and(|best| *best < cost) {
+            continue;
+        }
+
+        for next_orient in Orient::ALL {
+            let next = next_orient.move_in(position);
+            if !inside(next) || obstacles.contains(&next) {
+                continue;
+            }
+            let mut commands = orient.rotate_to(next_orient);
+            commands.push(ServerMessage::Move);
+
+            let next_cost = cost + commands.len() as u32;
+            let node = (next, next_orient);
+            if costs.get(&node).is_some_and(|best| *best <= next_cost) {
+                continue;
+            }
+            costs.insert(node, next_cost);
+            came_from.insert(node, ((position, orient), commands));
+            open.push(Reverse((next_cost + heuristic(next), next_cost, next, next_orient)));
+        }
+    }
+
+    None
+}
+
+fn unwind(came_from: &HashMap<Node, (Node, Vec<ServerMessage>)>, mut node: Node) -> VecDeque<ServerMessage> {
+    let mut plan = VecDeque::new();
+    while let Some((previous, commands)) = came_from.get(&node) {
+        for command in commands.iter().rev() {
+            plan.push_front(command.clone());
+        }
+        node = *previous;
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follows the plan and returns the cells it goes through
+    fn follow(plan: &[ServerMessage], start: (i32, i32), orient: Orient) -> Vec<(i32, i32)> {
+        let mut cells = vec![start];
+        let mut orient = orient;
+        for command in plan {
+            match command {
+                ServerMessage::Move => cells.push(orient.move_in(*cells.last().unwrap())),
+                ServerMessage::Left => orient = orient.left(),
+                ServerMessage::Right => orient = orient.right(),
+                _ => panic!("unexpected command {:?}", command),
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn open_grid_goes_straight() {
+        let plan = search((0, 3), Orient::SOUTH, (0, 0), &HashSet::new()).unwrap();
+        assert_eq!(Vec::from(plan), vec![ServerMessage::Move; 3]);
+
+        let plan = search((2, 0), Orient::NORTH, (0, 0), &HashSet::new()).unwrap();
+        assert_eq!(Vec::from(plan), vec![ServerMessage::Left, ServerMessage::Move, ServerMessage::Move]);
+
+        assert_eq!(search((0, 0), Orient::EAST, (0, 0), &HashSet::new()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn route_goes_around_cluster() {
+        let cluster = HashSet::from([(-1, 2), (0, 2), (1, 2), (2, 2), (-2, 2), (-1, 3), (0, 3), (1, 3)]);
+        let plan = Vec::from(search((0, 6), Orient::SOUTH, (0, 0), &cluster).unwrap());
+
+        let cells = follow(&plan, (0, 6), Orient::SOUTH);
+        assert_eq!(cells.last(), Some(&(0, 0)));
+        assert!(cells.iter().all(|cell| !cluster.contains(cell)), "{:?}", cells);
+        // Three moves out to x = 3, six down, three back and three turns
+        assert_eq!(plan.len(), 15, "{:?}", plan);
+    }
+
+    #[test]
+    fn enclosed_target_has_no_route() {
+        let ring = HashSet::from([(0, 1), (1, 0), (0, -1), (-1, 0)]);
+        assert_eq!(search((4, 4), Orient::WEST, (0, 0), &ring), None);
+
+        // Enclosed start
+        let ring = HashSet::from([(4, 5), (5, 4), (4, 3), (3, 4)]);
+        assert_eq!(search((4, 4), Orient::WEST, (0, 0), &ring), None);
+    }
+}
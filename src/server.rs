@@ -1,110 +1,180 @@
 
+use crate::codec::FrameDecoder;
+use crate::config::ServerConfig;
 use crate::constants::BTimeout;
 use crate::errors::BError;
+use crate::lockout::{self, RateLimit};
+use crate::logger::{self, LogContext};
 use crate::messages::{ClientMessage, ServerMessage};
+use crate::observer::Observer;
+use crate::shutdown::{SessionGuard, Shutdown, Verdict};
 use crate::state_machine::BState;
 
-use std::cmp::min;
-use std::{net::TcpStream, io::{Read, Write}};
+use std::{net::{TcpListener, TcpStream}, io::{self, Read, Write}, sync::Arc, thread, time::Duration};
 
 use crate::state_machine;
 
-pub fn handle_server(mut stream: TcpStream) {
-    stream.set_write_timeout(Some(BTimeout::Normal.value())).unwrap();
-    stream.set_read_timeout(Some(BTimeout::Normal.value())).unwrap();
-    stream.set_nodelay(true).unwrap();
+/// How often the accept loop checks for a shutdown request
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// Accepts connections and runs every session in its own thread
+/// until a shutdown is requested, then waits for the sessions to finish.
+pub fn serve(config: Arc<ServerConfig>, shutdown: Arc<Shutdown>) -> io::Result<()> {
+    let listener = TcpListener::bind(config.addr())?;
+    listener.set_nonblocking(true)?;
+
+    info!("Listening on {}", config.addr());
+    let mut rate = RateLimit::new(config.connection_rate);
+
+    while !shutdown.is_requested() {
+        let (stream, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if lockout::reject_connection(&mut rate, peer) {
+            continue;
+        }
+        if let Err(e) = stream.set_nonblocking(false) {
+            warn!("Failed to set up the connection from {}: {}", peer, e);
+            continue;
+        }
+
+        let config = Arc::clone(&config);
+        let mut session = shutdown.track();
+        let context = LogContext::new(Some(peer));
+        thread::spawn(move || {
+            let _log = logger::enter(context);
+            info!("Connection established!");
+            handle_server(stream, &config, &mut session);
+            info!("Connection closed!");
+        });
+    }
+
+    drop(listener);
+    info!("Shutting down, waiting for {} sessions", shutdown.active());
+    shutdown.wait_idle();
+    Ok(())
+}
+
+pub fn handle_server(mut stream: TcpStream, config: &ServerConfig, session: &mut SessionGuard) {
+    let context = logger::current_context()
+        .unwrap_or_else(|| LogContext::new(stream.peer_addr().ok()));
+    let mut observer = Observer::new(config, &context);
+
+    let setup = set_timeout(&stream, config.timeout(&BTimeout::Normal))
+        .and_then(|_| stream.set_nodelay(true).map_err(BError::Io));
+    if let Err(e) = setup {
+        observer.failed(&e, true, None);
+        server_send_error(&mut stream, &mut observer, e);
+        return;
+    }
 
     let mut state = BState::initial();
+    let mut decoder = FrameDecoder::new();
 
     loop {
+        match session.verdict(&state) {
+            Verdict::Continue => {}
+            Verdict::Logout => {
+                info!("Shutting down, logging the robot out");
+                session.abort();
+                observer.shutdown();
+                let res = server_send_message(&mut stream, &mut observer, ServerMessage::Logout);
+                server_finish(&stream, res);
+                return;
+            }
+            Verdict::Close => {
+                info!("Shutting down, closing the session");
+                session.abort();
+                observer.shutdown();
+                server_shutdown(&stream);
+                return;
+            }
+        }
+
         let max_len = state.expected_mess_lenth();
-        let res = read_message(&mut stream, max_len)
-            .and_then(|mess| state.handle_message(mess));
-
-        match res {
-            Ok((new_state, action)) => {
-                state = new_state;
-                match action {
-                    state_machine::PRes::SendMessage(message) => 
-                        server_send_message(&mut stream, message),
-
-                    state_machine::PRes::SendMessages(messages) =>
-                        for message in messages {
-                            server_send_message(&mut stream, message)
-                        },
-
-                    state_machine::PRes::UpdateTimeout(timeout) =>  {
-                        stream.set_write_timeout(Some(timeout.value())).unwrap();
-                        stream.set_read_timeout(Some(timeout.value())).unwrap();
-                    },
-
-                    state_machine::PRes::Finish(message, response) => {
-                        println!("The message was \"{}\"", message);
-                        server_send_message(&mut stream, response);
-                        server_shutdown(&stream);
-                        return;
-                    }
-                }
+        let login_key = state.login_key();
+        let res = match read_message(&mut stream, &mut decoder, max_len) {
+            Ok(mess) if lockout::reject_username(&state, &mess) => {
+                observer.client(&mess);
+                observer.locked_out();
+                server_shutdown(&stream);
+                return;
+            }
+            Ok(mess) => {
+                observer.client(&mess);
+                state.handle_message(mess, config)
+                    .inspect_err(|e| observer.failed(e, false, login_key))
+            }
+            Err(e) => {
+                observer.failed(&e, true, login_key);
+                Err(e)
             }
+        };
+
+        let (new_state, action) = match res {
+            Ok(res) => res,
             Err(e) => {
-                server_send_error(&mut stream, e);
+                server_send_error(&mut stream, &mut observer, e);
                 return;
             }
-        }
-    }
-}
+        };
+        state = new_state;
 
-fn check_max_len_overflow(max_len: usize, message: &Vec<u8>) -> bool {
-    let len = message.len();
-    (max_len - 2 < len) && !(message[max_len - 2] == 7u8 && max_len - 1 == len)
-}
+        let res = match action {
+            state_machine::PRes::SendMessage(message) =>
+                server_send_message(&mut stream, &mut observer, message),
+
+            state_machine::PRes::SendMessages(messages) =>
+                messages.into_iter()
+                    .try_for_each(|message| server_send_message(&mut stream, &mut observer, message)),
+
+            state_machine::PRes::UpdateTimeout(timeout) => {
+                observer.timeout(&timeout);
+                set_timeout(&stream, config.timeout(&timeout))
+            },
+
+            state_machine::PRes::Finish(messages, response, stats) => {
+                for message in &messages {
+                    info!("The message was \"{}\"", message);
+                }
+                info!("Path: {}", stats);
+                observer.finished(&stats);
+                let res = server_send_message(&mut stream, &mut observer, response);
+                server_finish(&stream, res);
+                return;
+            }
+        };
 
-fn prefix_match(msg1: &Vec<u8>, msg2: &Vec<u8>) -> bool {
-    let len = min(msg1.len(), msg2.len());
-    for i in 0..len {
-        if msg1[i] != msg2[i] {
-            return false
+        if let Err(e) = res {
+            observer.failed(&e, true, None);
+            server_send_error(&mut stream, &mut observer, e);
+            return;
         }
     }
-    true
 }
 
-fn read_message(stream: &mut TcpStream, max_len: usize) -> Result<ClientMessage, BError> {
-    let mut message = Vec::<u8>::new();
-    let recharching_bytes = "RECHARGING".as_bytes().to_vec();
-    let full_power_bytes = "FULL POWER".as_bytes().to_vec();
+fn read_message(stream: &mut TcpStream, decoder: &mut FrameDecoder, max_len: usize) -> Result<ClientMessage, BError> {
+    let mut bytes = [0; 256];
 
     loop {
-        let is_normal_overflow = check_max_len_overflow(max_len, &message);
-        let is_charging = !check_max_len_overflow(12, &message) && 
-            (prefix_match(&message, &recharching_bytes)
-             || prefix_match(&message, &full_power_bytes));
-
-        if is_normal_overflow && !is_charging {
-            let len = message.len();
-            return Err(BError::MessageToLong(String::from_utf8(message).unwrap(), len));
+        if let Some(message) = decoder.next_frame(max_len)? {
+            debug!("> Read: {}", message.0);
+            return Ok(message);
         }
 
-        let mut bytes = [0; 1];
         let bytes_num = unwrap_io(stream.read(&mut bytes))?;
         if bytes_num == 0 {
             return Err(BError::ConnectionClosed);
         }
-
-        let byte = bytes[0];
-        if byte == 8u8 { // \b
-            if let Some(last) = message.last() {
-                if last == &7u8 {
-                    message.pop();
-                    let str = String::from_utf8(message).unwrap();
-                    println!("> Read: {}", str);
-                    return Ok(ClientMessage(str));
-                }
-            }
-        }
-
-        // println!("? Push: {} - {}", byte, String::from_utf8(bytes.to_vec()).unwrap());
-        message.push(byte)
+        decoder.push(&bytes[..bytes_num]);
     }
 }
 
@@ -115,31 +185,46 @@ fn unwrap_io<T>(res: Result<T, std::io::Error>) -> Result<T, BError> {
     }
 }
 
-fn server_send_message(stream: &mut TcpStream, message: ServerMessage) {
-    let payload = message.to_payload();
+fn set_timeout(stream: &TcpStream, timeout: Duration) -> Result<(), BError> {
+    stream.set_write_timeout(Some(timeout))
+        .and_then(|_| stream.set_read_timeout(Some(timeout)))
+        .map_err(BError::Io)
+}
 
-    let str = String::from_utf8(payload.clone()).unwrap();
-    println!("# Send: {}", str);
+fn server_send_message(stream: &mut TcpStream, observer: &mut Observer<'_>, message: ServerMessage) -> Result<(), BError> {
+    observer.server(&message);
+    let payload = message.to_payload();
+    debug!("# Send: {}", String::from_utf8_lossy(&payload));
 
-    stream.write_all(&payload).unwrap();
+    stream.write_all(&payload).map_err(BError::WriteFailed)
 }
 
-fn server_send_error(stream: &mut TcpStream, error : BError) {
+fn server_send_error(stream: &mut TcpStream, observer: &mut Observer<'_>, error : BError) {
 
-    println!("Error: {:?}", error);
+    error!("Error: {}", error);
 
-    if error.should_send() {
+    let res = if error.should_send() {
         let to_send = error.server_response();
-        server_send_message(stream, to_send);
-    }
+        server_send_message(stream, observer, to_send)
+    } else {
+        Ok(())
+    };
 
+    server_finish(stream, res);
+}
+
+/// Shuts the stream down after the last message was sent
+fn server_finish(stream: &TcpStream, sent: Result<(), BError>) {
+    if let Err(e) = sent {
+        error!("Error: {}", e);
+    }
     server_shutdown(stream);
 }
 
 fn server_shutdown(stream: &TcpStream) {
-    println!("Stopping a stream");
+    debug!("Stopping a stream");
     match stream.shutdown(std::net::Shutdown::Both) {
         Ok(_) => {}
-        Err(e) => println!("Server didn't shudown as expected: {}", e),
+        Err(e) => warn!("Server didn't shudown as expected: {}", e),
     }
 }
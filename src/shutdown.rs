@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::state_machine::BState;
+
+/// Shared between the accept loop and all the sessions.
+///
+/// Once requested, no new connections are accepted, sessions still logging in
+/// are closed and sessions looking for the message get `grace` time to finish.
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    grace: Duration,
+    deadline: OnceLock<Instant>,
+    active: Mutex<usize>,
+    idle: Condvar,
+    drained: AtomicUsize,
+    aborted: AtomicUsize,
+}
+
+/// What a session should do before reading the next message
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Continue,
+    /// Send `106 LOGOUT` and close
+    Logout,
+    /// Just close the connection
+    Close,
+}
+
+impl Shutdown {
+
+    pub fn new(grace: Duration) -> Shutdown {
+        Shutdown {
+            requested: Arc::new(AtomicBool::new(false)),
+            grace,
+            deadline: OnceLock::new(),
+            active: Mutex::new(0),
+            idle: Condvar::new(),
+            drained: AtomicUsize::new(0),
+            aborted: AtomicUsize::new(0),
+        }
+    }
+
+    /// The flag to set from a signal handler
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.requested)
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// The grace period starts when the first party notices the request
+    fn deadline(&self) -> Instant {
+        *self.deadline.get_or_init(|| Instant::now() + self.grace)
+    }
+
+    pub fn verdict(&self, state: &BState) -> Verdict {
+        if !self.is_requested() {
+            Verdict::Continue
+        } else if !state.is_navigating() {
+            Verdict::Close
+        } else if Instant::now() >= self.deadline() {
+            Verdict::Logout
+        } else {
+            Verdict::Continue
+        }
+    }
+
+    /// Registers a new session, it is unregistered when the guard is dropped
+    pub fn track(self: &Arc<Self>) -> SessionGuard {
+        *self.active.lock().unwrap() += 1;
+        SessionGuard { shutdown: Arc::clone(self), aborted: false }
+    }
+
+    pub fn active(&self) -> usize {
+        *self.active.lock().unwrap()
+    }
+
+    /// Blocks until all the sessions are finished
+    pub fn wait_idle(&self) {
+        self.deadline();
+        let mut active = self.active.lock().unwrap();
+        while *active > 0 {
+            active = self.idle.wait(active).unwrap();
+        }
+    }
+
+    /// Sessions finished on their own and sessions stopped by the server
+    /// after the shutdown was requested
+    pub fn report(&self) -> (usize, usize) {
+        (self.drained.load(Ordering::SeqCst), self.aborted.load(Ordering::SeqCst))
+    }
+}
+
+pub struct SessionGuard {
+    shutdown: Arc<Shutdown>,
+    aborted: bool,
+}
+
+impl SessionGuard {
+    pub fn verdict(&self, state: &BState) -> Verdict {
+        self.shutdown.verdict(state)
+    }
+
+    /// Marks the session as stopped by the server
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let shutdown = &self.shutdown;
+        if shutdown.is_requested() {
+            let counter = if self.aborted { &shutdown.aborted } else { &shutdown.drained };
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let mut active = shutdown.active.lock().unwrap();
+        *active -= 1;
+        if *active == 0 {
+            shutdown.idle.notify_all();
+        }
+    }
+}
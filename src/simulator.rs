@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::auth::Authenticator;
+use crate::client::{ClientError, RobotClient};
+use crate::config::ServerConfig;
+use crate::messages::ServerMessage;
+use crate::path::Orient;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const CHARGING_TIME: Duration = Duration::from_millis(200);
+const SEGMENT_SIZE: usize = 3;
+const SEGMENT_DELAY: Duration = Duration::from_millis(20);
+
+/// What the server should do for the scenario to pass
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expect {
+    /// Pick up the messages at the mission's waypoints and log out
+    PickUp,
+    /// End the login with the given error
+    Error(ServerMessage),
+    /// Log the robot out without picking up, the target cannot be reached
+    GiveUp,
+}
+
+/// One simulated robot run, positions are relative to the first waypoint of the robot's mission
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub name: String,
+    pub username: String,
+    pub key: usize,
+    /// Confirmation offset, non-zero sends a wrong client confirmation
+    pub confirmation_offset: u32,
+    pub start: (i32, i32),
+    pub orient: Orient,
+    pub obstacles: Vec<(i32, i32)>,
+    pub secret_message: String,
+    /// Recharge before every n-th reply
+    pub recharge_every: Option<usize>,
+    /// Send every message in several small packets
+    pub segment: bool,
+    /// Send the charging messages and the reply in one packet
+    pub merge: bool,
+    /// Fail if the server needs more commands than this to reach the first waypoint,
+    /// every further waypoint of the mission adds as many plus its distance from the previous one.
+    /// By default twice the server's step budget for the whole session,
+    /// so a server giving up on a target is not taken for one that never stops
+    pub max_commands: Option<usize>,
+    pub expect: Expect,
+}
+
+impl Scenario {
+    pub fn new(name: &str, start: (i32, i32), orient: Orient) -> Scenario {
+        Scenario {
+            name: name.to_string(),
+            username: "Oompa Loompa".to_string(),
+            key: 0,
+            confirmation_offset: 0,
+            start,
+            orient,
+            obstacles: Vec::new(),
+            secret_message: "Tajny vzkaz".to_string(),
+            recharge_every: None,
+            segment: false,
+            merge: false,
+            max_commands: None,
+            expect: Expect::PickUp,
+        }
+    }
+}
+
+/// The scenarios run by `bobika simulate` when none is chosen
+pub fn scenarios() -> Vec<Scenario> {
+    let mut list = vec![
+        Scenario::new("ideal", (3, 4), Orient::NORTH),
+        Scenario::new("on-axis", (0, -6), Orient::EAST),
+        Scenario::new("at-target", (0, 0), Orient::WEST),
+    ];
+
+    let mut obstacles = Scenario::new("obstacles", (5, 3), Orient::SOUTH);
+    obstacles.obstacles = vec![(4, 3), (3, 3), (0, 2), (1, 1), (2, 0), (-1, 0), (0, -1)];
+    list.push(obstacles);
+
+    let mut cluster = Scenario::new("obstacle-cluster", (0, 6), Orient::SOUTH);
+    cluster.obstacles = vec![(-1, 3), (0, 3), (1, 3), (-1, 2), (0, 2), (1, 2), (2, 2), (-2, 2)];
+    cluster.max_commands = Some(40);
+    list.push(cluster);
+
+    let mut wall = Scenario::new("wall-on-axis", (0, 7), Orient::SOUTH);
+    wall.obstacles = vec![(0, 5), (0, 2)];
+    list.push(wall);
+
+    let mut walled = Scenario::new("walled-target", (3, 2), Orient::WEST);
+    walled.obstacles = vec![(0, 1), (1, 0), (0, -1), (-1, 0)];
+    walled.expect = Expect::GiveUp;
+    list.push(walled);
+
+    let mut recharging = Scenario::new("recharging", (-4, 2), Orient::EAST);
+    recharging.recharge_every = Some(5);
+    list.push(recharging);
+
+    let mut segmented = Scenario::new("segmentation", (2, -3), Orient::WEST);
+    segmented.segment = true;
+    list.push(segmented);
+
+    let mut merged = Scenario::new("merging", (-2, -5), Orient::NORTH);
+    merged.recharge_every = Some(3);
+    merged.merge = true;
+    list.push(merged);
+
+    let mut wrong_key = Scenario::new("key-out-of-range", (1, 1), Orient::NORTH);
+    wrong_key.key = 999;
+    wrong_key.expect = Expect::Error(ServerMessage::KeyOutOfRangeError);
+    list.push(wrong_key);
+
+    let mut wrong_hash = Scenario::new("wrong-confirmation", (1, 1), Orient::NORTH);
+    wrong_hash.confirmation_offset = 1;
+    wrong_hash.expect = Expect::Error(ServerMessage::LoginFailed);
+    list.push(wrong_hash);
+
+    list
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Passed { commands: usize },
+    Failed(String),
+}
+
+/// Connects to the server from the config and plays the robot
+pub fn run(config: &ServerConfig, scenario: &Scenario) -> Outcome {
+    match Robot::connect(config, scenario).and_then(|mut robot| robot.play()) {
+        Ok(outcome) => outcome,
+        Err(ClientError::Rejected(message)) if scenario.expect == Expect::Error(message.clone()) =>
+            Outcome::Passed { commands: 0 },
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// The server's keys with the client confirmation off by `offset` (a wrong secret in the hmac mode)
+struct WrongConfirmation {
+    keys: Arc<dyn Authenticator>,
+    offset: u32,
+}
+
+impl Authenticator for WrongConfirmation {
+    fn confirmations(&self, username: &str, index: usize) -> Option<(u32, u32)> {
+        let (server, client) = self.keys.confirmations(username, index)?;
+        Some((server, (client + self.offset) % 65536))
+    }
+
+    fn hmac_secret(&self, username: &str, index: usize) -> Option<String> {
+        let secret = self.keys.hmac_secret(username, index)?;
+        Some(format!("{}{}", secret, self.offset))
+    }
+}
+
+/// The command limit for the first waypoint scaled to the whole mission
+fn mission_limit(max: usize, waypoints: &[(i32, i32)]) -> usize {
+    let legs: u32 = waypoints.windows(2)
+        .map(|leg| leg[0].0.abs_diff(leg[1].0) + leg[0].1.abs_diff(leg[1].1))
+        .sum();
+    max * waypoints.len() + legs as usize
+}
+
+struct Robot<'a> {
+    scenario: &'a Scenario,
+    client: RobotClient,
+    max_commands: usize,
+    waypoints: Vec<(i32, i32)>,
+    picked_up: usize,
+    position: (i32, i32),
+    orient: Orient,
+    obstacles: HashSet<(i32, i32)>,
+    replies: usize,
+}
+
+impl Robot<'_> {
+
+    fn connect<'a>(config: &'a ServerConfig, scenario: &'a Scenario) -> Result<Robot<'a>, ClientError> {
+        // The server's authenticator, so a reloaded key file or per robot keys are used too
+        let mut keys = Arc::clone(&config.authenticator);
+        if scenario.confirmation_offset != 0 {
+            keys = Arc::new(WrongConfirmation { keys, offset: scenario.confirmation_offset });
+        }
+        let mut client = RobotClient::connect(&config.addr(), READ_TIMEOUT)?
+            .with_authenticator(keys, config.auth_mode);
+        if scenario.segment {
+            client = client.with_segments(SEGMENT_SIZE, SEGMENT_DELAY);
+        }
+
+        let waypoints = config.mission_for(&scenario.username).waypoints;
+        let (tx, ty) = waypoints[0];
+        let shift = |(x, y): (i32, i32)| (x + tx, y + ty);
+
+        Ok(Robot {
+            scenario,
+            client,
+            max_commands: match scenario.max_commands {
+                Some(max) => mission_limit(max, &waypoints),
+                None => 2 * config.step_budget as usize,
+            },
+            waypoints,
+            picked_up: 0,
+            position: shift(scenario.start),
+            orient: scenario.orient,
+            obstacles: scenario.obstacles.iter().copied().map(shift).collect(),
+            replies: 0,
+        })
+    }
+
+    fn play(&mut self) -> Result<Outcome, ClientError> {
+        self.client.login(&self.scenario.username, self.scenario.key)?;
+        if let Expect::Error(expected) = &self.scenario.expect {
+            return Ok(Outcome::Failed(format!("logged in, expected \"{}\"", expected.body())));
+        }
+
+        self.navigate()
+    }
+
+    fn navigate(&mut self) -> Result<Outcome, ClientError> {
+        let mut commands = 0;
+        loop {
+            let command = self.client.next_command()?;
+            commands += 1;
+            if commands > self.max_commands {
+                return Ok(Outcome::Failed(format!("more than {} commands", self.max_commands)));
+            }
+
+            match command {
+                ServerMessage::Move => {
+                    let next = self.orient.move_in(self.position);
+                    if !self.obstacles.contains(&next) {
+                        self.position = next;
+                    }
+                }
+                ServerMessage::Left => self.orient = self.orient.left(),
+                ServerMessage::Right => self.orient = self.orient.right(),
+                ServerMessage::Logout if self.scenario.expect == Expect::GiveUp => return Ok(Outcome::Passed { commands }),
+                ServerMessage::PickUp => {
+                    if let Some(outcome) = self.pick_up(commands)? {
+                        return Ok(outcome);
+                    }
+                    continue;
+                },
+                _ => return Ok(self.unexpected(&command)),
+            }
+
+            let position = self.position;
+            self.reply(|client| client.report(position))?;
+        }
+    }
+
+    /// Replies with the message, returns the outcome once the last one is picked up
+    fn pick_up(&mut self, commands: usize) -> Result<Option<Outcome>, ClientError> {
+        if self.waypoints.get(self.picked_up) != Some(&self.position) {
+            return Ok(Some(Outcome::Failed(format!("picking up at {:?}", self.position))));
+        }
+        self.picked_up += 1;
+
+        let message = format!("{} {}", self.scenario.secret_message, self.picked_up);
+        self.reply(|client| client.send_message(&message))?;
+        if self.picked_up < self.waypoints.len() {
+            return Ok(None);
+        }
+
+        match self.client.next_command()? {
+            ServerMessage::Logout => Ok(Some(Outcome::Passed { commands })),
+            command => Ok(Some(self.unexpected(&command))),
+        }
+    }
+
+    fn unexpected(&self, command: &ServerMessage) -> Outcome {
+        Outcome::Failed(format!("unexpected \"{}\" at {:?}", command.body(), self.position))
+    }
+
+    /// Sends a reply to a command, recharging first if it is the time
+    fn reply(&mut self, answer: impl FnOnce(&mut RobotClient) -> Result<(), ClientError>) -> Result<(), ClientError> {
+        self.replies += 1;
+        let recharge = self.scenario.recharge_every
+            .is_some_and(|every| self.replies.is_multiple_of(every));
+
+        if recharge {
+            self.client.recharging()?;
+            thread::sleep(CHARGING_TIME);
+            if self.scenario.merge {
+                self.client.hold();
+            }
+            self.client.full_power()?;
+        }
+        answer(&mut self.client)
+    }
+}
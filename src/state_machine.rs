@@ -1,22 +1,36 @@
 
+use crate::config::ServerConfig;
 use crate::constants::{BTimeout, ServerSecret};
 use crate::errors::BError;
+use crate::hmac_auth::{self, AuthMode};
 use crate::messages::{ServerMessage, ClientMessage};
-use crate::path::PathState;
+use crate::mission::{Mission, Progress};
+use crate::path::{self, Orient, PathState};
+use crate::stats::PathStats;
 
 pub enum PRes {
     SendMessage(ServerMessage),
     SendMessages(Vec<ServerMessage>),
     UpdateTimeout(BTimeout),
-    Finish(String, ServerMessage),
+    /// The picked up messages, the response and the commands it took
+    Finish(Vec<String>, ServerMessage, PathStats),
+}
+
+/// What the robot has to answer to log in
+pub enum Challenge {
+    /// The client confirmation of the classic login
+    Hash(u32),
+    /// The nonce sent in the HMAC mode
+    Nonce(String),
 }
 
 pub enum BState {
     LoginUsername,
     LoginKey { username: String },
-    LoginValidation { expected_hash: u32 },
-    FindPath(PathState),
-    Extract,
+    LoginValidation { username: String, challenge: Challenge, key: usize, mission: Mission },
+    FindPath(PathState, Progress),
+    /// Waiting for the message at the waypoint the robot is at
+    Extract { progress: Progress, position: (i32, i32), orient: Option<Orient> },
     Recharging(Box<BState>),
 }
 
@@ -26,18 +40,37 @@ impl BState {
         BState::LoginUsername
     }
 
+    /// The robot is logged in and the server is looking for the message
+    pub fn is_navigating(&self) -> bool {
+        match self {
+            Self::FindPath(..) | Self::Extract {..} => true,
+            Self::Recharging(state) => state.is_navigating(),
+            _ => false,
+        }
+    }
+
+    /// The key index the robot is logging in with, while its confirmation is awaited
+    pub fn login_key(&self) -> Option<usize> {
+        match self {
+            Self::LoginValidation { key, .. } => Some(*key),
+            Self::Recharging(state) => state.login_key(),
+            _ => None,
+        }
+    }
+
     pub fn expected_mess_lenth(&self) -> usize {
         match self {
             Self::LoginUsername => 20,
             Self::LoginKey {..} => 5,
+            Self::LoginValidation { challenge: Challenge::Nonce(_), .. } => hmac_auth::ANSWER_LEN + 2,
             Self::LoginValidation {..} => 7,
-            Self::FindPath(_) => 12,
+            Self::FindPath(..) => 12,
             Self::Recharging(_) => 12,
-            Self::Extract => 100,
+            Self::Extract {..} => 100,
         }
     }
 
-    pub fn handle_message(self, message: ClientMessage) -> Result<(BState, PRes), BError> {
+    pub fn handle_message(self, message: ClientMessage, config: &ServerConfig) -> Result<(BState, PRes), BError> {
         
         match message.0.as_str() {
             "RECHARGING" => {
@@ -58,10 +91,13 @@ impl BState {
         match self {
             Self::LoginUsername => {
 
-                println!("x Mach: Processing username");
-                println!("x Mach: Requesting key index");
+                debug!("x Mach: Processing username");
+                debug!("x Mach: Requesting key index");
 
                 let username = message.0;
+                if !config.authenticator.accepts(&username) {
+                    return Err(BError::UnknownRobot(username));
+                }
                 let next_state = Self::LoginKey { username };
                 let message = PRes::SendMessage(ServerMessage::KeyRequest);
 
@@ -69,55 +105,88 @@ impl BState {
             }
             Self::LoginKey { username } => {
 
-                println!("x Mach: Processing key");
-                println!("x Mach: Sending hash");
+                debug!("x Mach: Processing key");
+                debug!("x Mach: Sending hash");
 
                 let key = parse_key_id(&message.0)?;
 
-                let secrets = ServerSecret::secrets();
                 let index = usize::try_from(key)
                     .map_err(|_| BError::InvalidKeyIndex(key))?;
-                let secret = secrets.get(index)
-                    .ok_or(BError::InvalidKeyIndex(key))?;
-
-                login_hash("Mnau!", &ServerSecret{s:54621, c:45328});
-                let hash = login_hash(&username, secret);
-
-                let next_state = Self::LoginValidation { expected_hash: hash.1 };
-                let message = PRes::SendMessage(ServerMessage::Confirm(hash.0));
-
-                Ok((next_state, message))
+                let (challenge, message) = match config.auth_mode {
+                    AuthMode::Classic => {
+                        let hash = config.authenticator.confirmations(&username, index)
+                            .ok_or(BError::InvalidKeyIndex(key))?;
+                        (Challenge::Hash(hash.1), ServerMessage::Confirm(hash.0))
+                    },
+                    AuthMode::Hmac => {
+                        if config.authenticator.hmac_secret(&username, index).is_none() {
+                            return Err(BError::InvalidKeyIndex(key));
+                        }
+                        let nonce = hmac_auth::nonce()?;
+                        (Challenge::Nonce(nonce.clone()), ServerMessage::Nonce(nonce))
+                    },
+                };
+
+                let mission = config.mission_for(&username);
+                let next_state = Self::LoginValidation { username, challenge, key: index, mission };
+
+                Ok((next_state, PRes::SendMessage(message)))
             }
-            Self::LoginValidation { expected_hash } => {
-
-                println!("x Mach: Validating hash");
-                println!("x Mach: Sending ok");
-
-                let client_hash = parse_confirmation(&message.0)?;
-
-                if expected_hash != client_hash.try_into().unwrap_or(100_000u32) {
-                    return Err(BError::HashMismatch {
-                        expected: expected_hash, actual: client_hash.try_into().unwrap()
-                    })
+            Self::LoginValidation { username, challenge, key, mission } => {
+
+                debug!("x Mach: Validating hash");
+                debug!("x Mach: Sending ok");
+
+                match challenge {
+                    Challenge::Hash(expected_hash) => {
+                        let client_hash = parse_confirmation(&message.0)?;
+
+                        let client_hash = u32::try_from(client_hash)
+                            .map_err(|_| BError::InvalidConfirmation(client_hash))?;
+
+                        config.authenticator.validate(&username, expected_hash, client_hash)?;
+                    },
+                    Challenge::Nonce(nonce) => {
+                        match config.authenticator.hmac_secret(&username, key) {
+                            Some(secret) if hmac_auth::verify(&secret, &username, &nonce, &message.0) => {},
+                            _ => return Err(BError::AnswerMismatch),
+                        }
+                    },
                 }
 
-                let next_state = Self::FindPath(PathState::FindingPosition);
+                let (target, mut progress) = Progress::start(&mission);
+                progress.stats.command(&ServerMessage::Move);
+
+                let next_state = Self::FindPath(PathState::FindingPosition { target }, progress);
                 let message = PRes::SendMessages(
-                    vec![ServerMessage::NoProblemo, ServerMessage::Left]
+                    vec![ServerMessage::NoProblemo, ServerMessage::Move]
                     );
 
                 Ok((next_state, message))
             }
-            Self::FindPath(state) => state.handle_message(message),
-            Self::Extract => Ok((self, PRes::Finish(message.0, ServerMessage::Logout))),
+            Self::FindPath(state, progress) => state.handle_message(message, config, progress),
+            Self::Extract { mut progress, position, orient } => {
+                progress.messages.push(message.0);
+
+                match progress.remaining.pop_front() {
+                    Some(target) => path::next_waypoint(position, orient, target, progress, config),
+                    None => {
+                        debug!("x Mach: Mission complete");
+
+                        let res = PRes::Finish(progress.messages.clone(), ServerMessage::Logout, progress.stats);
+                        Ok((Self::Extract { progress, position, orient }, res))
+                    }
+                }
+            },
             Self::Recharging(_) => Err(BError::MessageWhileCharging),
         }
     }
 }
 
-fn login_hash(username: &str, secret: &ServerSecret) -> (u32, u32){
+/// Returns the server and the client confirmation for the username
+pub fn login_hash(username: &str, secret: &ServerSecret) -> (u32, u32){
     let modulo = 65536u32;
-    let sum = username.bytes().map(Into::<u32>::into).fold(0u32, |acu, x| acu + x);
+    let sum = username.bytes().map(Into::<u32>::into).sum::<u32>();
     let core = (sum * 1000) % modulo;
     let ServerSecret { c, s } = secret;
     ((s + core) % modulo, (c + core) % modulo)
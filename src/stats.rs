@@ -0,0 +1,139 @@
+use std::fmt;
+
+use crate::messages::ServerMessage;
+
+/// Commands the server needed to get the robot to the message
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathStats {
+    pub moves: u32,
+    pub turns: u32,
+    /// Moves that did not change the position
+    pub blocked: u32,
+    /// Manhattan distance of the first reported position from the target
+    /// (summed over all the waypoints), the least number of moves any robot could do it in.
+    /// The robot reports its first position after the move sent with the login,
+    /// that move is counted in `moves` but not in the distance.
+    pub distance: Option<u32>,
+    position: Option<(i32, i32)>,
+    moving: bool,
+}
+
+impl PathStats {
+
+    pub fn new() -> PathStats {
+        PathStats::default()
+    }
+
+    /// Called with the position the robot starts heading to a target from
+    pub fn start(&mut self, position: (i32, i32), target: (i32, i32)) {
+        let distance = position.0.abs_diff(target.0) + position.1.abs_diff(target.1);
+        self.distance = Some(self.distance.unwrap_or(0) + distance);
+    }
+
+    /// Called with every position the robot reports
+    pub fn report(&mut self, position: (i32, i32)) {
+        if self.moving && self.position == Some(position) {
+            self.blocked += 1;
+        }
+        self.position = Some(position);
+    }
+
+    /// Called with every message sent while navigating
+    pub fn command(&mut self, message: &ServerMessage) {
+        match message {
+            ServerMessage::Move => self.moves += 1,
+            ServerMessage::Left | ServerMessage::Right => self.turns += 1,
+            _ => {}
+        }
+        self.moving = message == &ServerMessage::Move;
+    }
+
+    pub fn commands(&self) -> u32 {
+        self.moves + self.turns
+    }
+
+    /// Commands sent on top of the theoretical minimum,
+    /// at least one because of the first move (see `distance`)
+    pub fn extra(&self) -> u32 {
+        self.commands().saturating_sub(self.distance.unwrap_or(0))
+    }
+}
+
+impl fmt::Display for PathStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} commands ({} moves, {} turns, {} blocked), {} at least, {} extra",
+            self.commands(), self.moves, self.turns, self.blocked, self.distance.unwrap_or(0), self.extra())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The stats of a robot that reports the positions after the commands
+    fn replay(start: (i32, i32), target: (i32, i32), steps: &[(ServerMessage, (i32, i32))]) -> PathStats {
+        let mut stats = PathStats::new();
+        // The move sent with the login, its reply is the first position
+        stats.command(&ServerMessage::Move);
+        stats.report(start);
+        stats.start(start, target);
+        for (command, position) in steps {
+            stats.command(command);
+            stats.report(*position);
+        }
+        stats
+    }
+
+    #[test]
+    fn blocked_moves_keep_the_position() {
+        let stats = replay((2, 0), (0, 0), &[
+            (ServerMessage::Move, (2, 0)),
+            (ServerMessage::Left, (2, 0)),
+            (ServerMessage::Move, (2, -1)),
+            (ServerMessage::Move, (2, -1)),
+            (ServerMessage::Right, (2, -1)),
+        ]);
+
+        // Turns report the same position without being blocked
+        assert_eq!((stats.moves, stats.turns, stats.blocked), (4, 2, 2));
+    }
+
+    #[test]
+    fn distance_is_from_the_first_reported_position() {
+        let stats = replay((3, -4), (1, 1), &[(ServerMessage::Move, (2, -4))]);
+        assert_eq!(stats.distance, Some(7));
+
+        let mut mission = stats;
+        mission.start((2, -4), (2, 0));
+        assert_eq!(mission.distance, Some(11), "every waypoint adds its distance");
+    }
+
+    #[test]
+    fn commands_and_extra() {
+        let stats = replay((0, 2), (0, 0), &[
+            (ServerMessage::Move, (0, 1)),
+            (ServerMessage::Move, (0, 0)),
+        ]);
+        assert_eq!(stats.commands(), 3);
+        assert_eq!(stats.extra(), 1, "the first move is always extra");
+
+        let detour = replay((0, 2), (0, 0), &[
+            (ServerMessage::Move, (0, 2)),
+            (ServerMessage::Right, (0, 2)),
+            (ServerMessage::Move, (1, 2)),
+        ]);
+        assert_eq!(detour.commands(), 4);
+        assert_eq!(detour.extra(), 2);
+        assert_eq!(PathStats::new().extra(), 0);
+    }
+
+    #[test]
+    fn summary_line() {
+        let stats = replay((1, 1), (0, 0), &[
+            (ServerMessage::Move, (1, 1)),
+            (ServerMessage::Left, (1, 1)),
+            (ServerMessage::Move, (0, 1)),
+        ]);
+        assert_eq!(stats.to_string(), "4 commands (3 moves, 1 turns, 1 blocked), 2 at least, 2 extra");
+    }
+}
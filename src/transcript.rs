@@ -0,0 +1,255 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::ServerConfig;
+use crate::hmac_auth;
+use crate::logger::LogContext;
+use crate::messages::{ClientMessage, ServerMessage};
+use crate::state_machine::{BState, Challenge, PRes};
+
+/// One line of a transcript.
+///
+/// Lines look like `<millis> <tag> <payload>` where the tag is `C` for a client message,
+/// `S` for a server message and `X` for the session being ended outside of the state machine
+/// (IO error, too long message, shutdown). Payloads are escaped, see [`escape`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Client(String),
+    Server(String),
+    Closed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub at: Duration,
+    pub entry: Entry,
+}
+
+/// Writes the transcript of one session, does nothing if disabled.
+pub struct Recorder {
+    out: Option<BufWriter<File>>,
+    start: Instant,
+}
+
+impl Recorder {
+
+    pub fn disabled() -> Recorder {
+        Recorder { out: None, start: Instant::now() }
+    }
+
+    /// Records into `session-<id>.log` in the config's transcript directory, if set
+    pub fn for_session(config: &ServerConfig, context: &LogContext) -> Recorder {
+        let Some(dir) = &config.record_dir else {
+            return Recorder::disabled();
+        };
+
+        match Recorder::create(dir, context) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                warn!("Failed to create a transcript in {}: {}", dir, e);
+                Recorder::disabled()
+            }
+        }
+    }
+
+    pub fn create(dir: &str, context: &LogContext) -> io::Result<Recorder> {
+        let path = Path::new(dir).join(format!("session-{}.log", context.session));
+        let mut out = BufWriter::new(File::create(path)?);
+
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or(0);
+        let peer = context.peer.map(|peer| peer.to_string()).unwrap_or_else(|| "-".to_string());
+        writeln!(out, "# bobika transcript session={} peer={} started={}", context.session, peer, started)?;
+        out.flush()?;
+
+        Ok(Recorder { out: Some(out), start: Instant::now() })
+    }
+
+    pub fn client(&mut self, message: &ClientMessage) {
+        self.write('C', &message.0);
+    }
+
+    pub fn server(&mut self, message: &ServerMessage) {
+        self.write('S', &message.body());
+    }
+
+    pub fn closed(&mut self, reason: &str) {
+        self.write('X', reason);
+    }
+
+    fn write(&mut self, tag: char, payload: &str) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+
+        let at = self.start.elapsed().as_millis();
+        let res = writeln!(out, "{} {} {}", at, tag, escape(payload))
+            .and_then(|_| out.flush());
+
+        if let Err(e) = res {
+            warn!("Failed to write the transcript, stopping recording: {}", e);
+            self.out = None;
+        }
+    }
+}
+
+/// Escapes backslashes and control characters (`\a\b` included) as `\\` and `\xNN`
+pub fn escape(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_ascii_control() => write!(escaped, "\\x{:02x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn unescape(str: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(str.len());
+    let mut chars = str.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                let code = u8::from_str_radix(&hex, 16).ok()?;
+                unescaped.push(char::from(code));
+            }
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+pub fn load(path: &str) -> io::Result<Vec<Record>> {
+    let content = fs::read_to_string(path)?;
+    let invalid = |index: usize| io::Error::new(
+        io::ErrorKind::InvalidData, format!("invalid transcript line {}", index + 1)
+    );
+
+    let mut records = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(3, ' ');
+        let at = parts.next().and_then(|at| at.parse().ok()).ok_or_else(|| invalid(index))?;
+        let tag = parts.next().ok_or_else(|| invalid(index))?;
+        let payload = parts.next().and_then(unescape).ok_or_else(|| invalid(index))?;
+
+        let entry = match tag {
+            "C" => Entry::Client(payload),
+            "S" => Entry::Server(payload),
+            "X" => Entry::Closed(payload),
+            _ => return Err(invalid(index)),
+        };
+        records.push(Record { at: Duration::from_millis(at), entry });
+    }
+    Ok(records)
+}
+
+/// A client message the server now answers differently than in the transcript
+#[derive(Debug)]
+pub struct Mismatch {
+    pub step: usize,
+    pub client: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub steps: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Feeds the recorded client messages through a new [`BState`]
+/// and compares the responses with the recorded server messages.
+/// Replay stops at the first `X` entry.
+///
+/// The nonce of the hmac login is random, any nonce matches the recorded one
+/// and the replayed session continues with the recorded nonce, so the recorded answer still verifies.
+pub fn replay(records: &[Record], config: &ServerConfig) -> ReplayReport {
+    let mut report = ReplayReport::default();
+    let mut state = Some(BState::initial());
+
+    let mut records = records.iter().peekable();
+    while let Some(record) = records.next() {
+        let client = match &record.entry {
+            Entry::Client(client) => client.clone(),
+            Entry::Server(_) => continue,
+            Entry::Closed(_) => break,
+        };
+
+        let mut expected = Vec::new();
+        while let Some(Record { entry: Entry::Server(server), .. }) = records.peek() {
+            expected.push(server.clone());
+            records.next();
+        }
+
+        let mut actual = match state.take() {
+            Some(current) => respond(current, &client, config, &mut state),
+            None => vec!["<session already finished>".to_string()],
+        };
+        adopt_nonce(&mut state, &expected, &mut actual);
+
+        report.steps += 1;
+        if actual != expected {
+            report.mismatches.push(Mismatch { step: report.steps, client, expected, actual });
+        }
+    }
+
+    report
+}
+
+/// Replaces a fresh nonce with the recorded one, in the response and in the state
+fn adopt_nonce(state: &mut Option<BState>, expected: &[String], actual: &mut Vec<String>) {
+    let Some(BState::LoginValidation { challenge: Challenge::Nonce(nonce), .. }) = state else {
+        return;
+    };
+    match (expected, actual.as_slice()) {
+        ([recorded], [fresh]) if fresh == nonce && hmac_auth::is_nonce(recorded) => {
+            *nonce = recorded.clone();
+            *actual = vec![recorded.clone()];
+        },
+        _ => {}
+    }
+}
+
+fn respond(state: BState, client: &str, config: &ServerConfig, next: &mut Option<BState>) -> Vec<String> {
+    let messages = match state.handle_message(ClientMessage(client.to_string()), config) {
+        Ok((new_state, action)) => match action {
+            PRes::SendMessage(message) => {
+                *next = Some(new_state);
+                vec![message]
+            },
+            PRes::SendMessages(messages) => {
+                *next = Some(new_state);
+                messages
+            },
+            PRes::UpdateTimeout(_) => {
+                *next = Some(new_state);
+                vec![]
+            },
+            PRes::Finish(_, response, _) => vec![response],
+        },
+        Err(e) if e.should_send() => vec![e.server_response()],
+        Err(_) => vec![],
+    };
+
+    messages.iter().map(ServerMessage::body).collect()
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use crate::messages::ServerMessage;
+use crate::path::Orient;
+
+/// Maps larger than this in either direction are not drawn
+const MAX_SIDE: i32 = 80;
+
+/// What a session revealed about the grid, rebuilt from the messages exchanged
+#[derive(Debug, Default)]
+pub struct Track {
+    pub username: Option<String>,
+    pub path: Vec<(i32, i32)>,
+    pub obstacles: HashSet<(i32, i32)>,
+    /// Positions the message was picked up at
+    pub pickups: Vec<(i32, i32)>,
+    pub orient: Option<Orient>,
+    last: Option<ServerMessage>,
+}
+
+impl Track {
+
+    pub fn new() -> Track {
+        Track::default()
+    }
+
+    pub fn server(&mut self, body: &str) {
+        let command = [ServerMessage::Move, ServerMessage::Left, ServerMessage::Right, ServerMessage::PickUp]
+            .into_iter()
+            .find(|command| command.body() == body);
+        let Some(command) = command else {
+            return;
+        };
+
+        match (&command, self.orient) {
+            (ServerMessage::Left, Some(orient)) => self.orient = Some(orient.left()),
+            (ServerMessage::Right, Some(orient)) => self.orient = Some(orient.right()),
+            (ServerMessage::PickUp, _) => self.pickups.extend(self.path.last()),
+            _ => {}
+        }
+        self.last = Some(command);
+    }
+
+    pub fn client(&mut self, message: &str) {
+        if message == "RECHARGING" || message == "FULL POWER" {
+            return;
+        }
+        if self.username.is_none() {
+            self.username = Some(message.to_string());
+            return;
+        }
+        if self.last == Some(ServerMessage::PickUp) {
+            self.last = None;
+            return;
+        }
+        let Some(position) = parse_position(message) else {
+            return;
+        };
+
+        match (self.path.last().copied(), &self.last) {
+            (Some(previous), Some(ServerMessage::Move)) if previous == position => {
+                if let Some(orient) = self.orient {
+                    self.obstacles.insert(orient.move_in(position));
+                }
+            },
+            (Some(previous), Some(ServerMessage::Move)) => {
+                self.orient = Orient::ALL.into_iter().find(|orient| orient.move_in(previous) == position);
+                self.path.push(position);
+            },
+            (previous, _) if previous != Some(position) => self.path.push(position),
+            _ => {}
+        }
+    }
+
+    /// Draws the grid with north up: `S` start, `*` path, `#` obstacles,
+    /// `T` targets, `$` pickups and the robot as an arrow (`@` if its heading is unknown)
+    pub fn render(&self, targets: &[(i32, i32)]) -> String {
+        let cells: Vec<(i32, i32)> = self.path.iter()
+            .chain(&self.obstacles)
+            .chain(targets)
+            .copied()
+            .collect();
+        let (Some(min_x), Some(max_x)) = (cells.iter().map(|c| c.0).min(), cells.iter().map(|c| c.0).max()) else {
+            return "Nothing to draw, the robot never reported its position\n".to_string();
+        };
+        let min_y = cells.iter().map(|c| c.1).min().unwrap_or(0);
+        let max_y = cells.iter().map(|c| c.1).max().unwrap_or(0);
+        if max_x - min_x >= MAX_SIDE || max_y - min_y >= MAX_SIDE {
+            return format!("The map is too large to draw, x {}..{}, y {}..{}\n", min_x, max_x, min_y, max_y);
+        }
+
+        let visited: HashSet<&(i32, i32)> = self.path.iter().collect();
+        let mut out = String::new();
+        writeln!(out, "x {}..{}, y {}..{}", min_x, max_x, min_y, max_y).unwrap();
+
+        for y in (min_y - 1..=max_y + 1).rev() {
+            for x in min_x - 1..=max_x + 1 {
+                let cell = (x, y);
+                let char = if self.path.last() == Some(&cell) {
+                    match self.orient {
+                        Some(Orient::NORTH) => '^',
+                        Some(Orient::SOUTH) => 'v',
+                        Some(Orient::EAST) => '>',
+                        Some(Orient::WEST) => '<',
+                        None => '@',
+                    }
+                } else if self.obstacles.contains(&cell) {
+                    '#'
+                } else if self.pickups.contains(&cell) {
+                    '$'
+                } else if targets.contains(&cell) {
+                    'T'
+                } else if self.path.first() == Some(&cell) {
+                    'S'
+                } else if visited.contains(&cell) {
+                    '*'
+                } else {
+                    '.'
+                };
+                out.push(char);
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn parse_position(message: &str) -> Option<(i32, i32)> {
+    let (x, y) = message.strip_prefix("OK ")?.split_once(' ')?;
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "async")]
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use bobika::async_server::serve;
+use bobika::{Orient, RobotClient, Shutdown};
+
+mod common;
+
+/// Runs the async server on a free port, returns its address
+fn start(args: &[&str], shutdown: &Arc<Shutdown>) -> (String, JoinHandle<()>) {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port().to_string();
+    let mut args = args.to_vec();
+    args.extend(["--host", "127.0.0.1", "--port", &port]);
+    let config = Arc::new(common::config(&args));
+    let addr = config.addr();
+
+    let shutdown = Arc::clone(shutdown);
+    let server = thread::spawn(move || {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(serve(config, shutdown))
+            .unwrap();
+    });
+    (addr, server)
+}
+
+fn connect(addr: &str) -> RobotClient {
+    for _ in 0..50 {
+        if let Ok(client) = RobotClient::connect(addr, Duration::from_secs(2)) {
+            return client;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("the server does not listen on {}", addr);
+}
+
+#[test]
+fn session_runs_on_the_runtime() {
+    let shutdown = Arc::new(Shutdown::new(Duration::from_secs(1)));
+    let (addr, server) = start(&["--target", "1,2"], &shutdown);
+
+    let mut client = connect(&addr);
+    client.login("Oompa Loompa", 0).unwrap();
+    client.recharging().unwrap();
+    client.full_power().unwrap();
+    assert_eq!(common::navigate(&mut client, (-2, 3), Orient::EAST), (1, 2));
+
+    shutdown.request();
+    server.join().unwrap();
+    assert_eq!(shutdown.active(), 0);
+}
+
+#[test]
+fn max_sessions_are_enforced() {
+    let shutdown = Arc::new(Shutdown::new(Duration::from_secs(1)));
+    let (addr, server) = start(&["--max-sessions", "1"], &shutdown);
+
+    let mut first = connect(&addr);
+    first.login("Oompa Loompa", 0).unwrap();
+
+    // The second connection waits in the backlog until the first session finishes
+    let mut second = TcpStream::connect(&addr).unwrap();
+    second.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    second.write_all(b"Mnau!\x07\x08").unwrap();
+    let mut reply = [0; 32];
+    let error = second.read(&mut reply).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut), "{}", error);
+    assert_eq!(shutdown.active(), 1);
+
+    assert_eq!(common::navigate(&mut first, (0, 1), Orient::SOUTH), (0, 0));
+    second.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let read = second.read(&mut reply).unwrap();
+    assert_eq!(&reply[..read], b"107 KEY REQUEST\x07\x08");
+
+    drop(second);
+    shutdown.request();
+    server.join().unwrap();
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::fs;
+
+use bobika::auth::{AllowList, Authenticator, ByteSum, KeyFile, RobotKeys};
+use bobika::config::ConfigError;
+use bobika::{BError, BState, PRes, ServerConfig, ServerMessage, ServerSecret};
+
+mod common;
+
+use common::{send_to, temp_file, try_config};
+
+#[test]
+fn robot_keys_are_per_robot() {
+    let path = temp_file("robots.ini", "[Oompa Loompa]\n0 = 23019 32037\n\n[Mnau!]\n0 = 1 2\n1 = 3 4\n");
+    let keys = RobotKeys::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(keys.accepts("Oompa Loompa"));
+    assert!(!keys.accepts("Pes"));
+    assert_eq!(keys.confirmations("Oompa Loompa", 0), Some((64907, 8389)));
+    assert_eq!(keys.confirmations("Oompa Loompa", 1), None);
+    assert!(keys.confirmations("Mnau!", 1).is_some());
+    assert_eq!(keys.confirmations("Pes", 0), None);
+}
+
+#[test]
+fn invalid_robot_keys_are_rejected() {
+    let cases = [
+        ("duplicate", "[Mnau!]\n0 = 1 2\n[Mnau!]\n0 = 3 4\n", 3),
+        ("orphan", "0 = 1 2\n[Mnau!]\n0 = 3 4\n", 1),
+        ("empty", "[]\n0 = 1 2\n", 1),
+    ];
+    for (name, content, line) in cases {
+        let path = temp_file(&format!("robots-{}.ini", name), content);
+        let result = RobotKeys::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::Syntax { line: l, .. }) if l == line), "{}", name);
+    }
+
+    let path = temp_file("robots-gap.ini", "[Mnau!]\n0 = 1 2\n2 = 3 4\n");
+    let result = RobotKeys::load(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(ConfigError::InvalidKeyTable(ref reason)) if reason == "missing key ID 1 of Mnau!"));
+}
+
+#[test]
+fn allow_list_filters_usernames() {
+    let usernames = HashSet::from(["Oompa Loompa".to_string()]);
+    let auth = AllowList::new(usernames, Box::new(ByteSum::new(ServerSecret::secrets())));
+
+    assert!(auth.accepts("Oompa Loompa"));
+    assert!(!auth.accepts("Mnau!"));
+    assert_eq!(auth.confirmations("Oompa Loompa", 0), Some((64907, 8389)));
+    assert!(matches!(auth.validate("Oompa Loompa", 1, 2), Err(BError::HashMismatch { expected: 1, actual: 2 })));
+}
+
+#[test]
+fn unknown_robot_gets_login_failed() {
+    let path = temp_file("allowed.txt", "# robots\nOompa Loompa\n");
+    let mut config = ServerConfig { allow_list: Some(path.clone()), ..ServerConfig::default() };
+    config.build_authenticator().unwrap();
+
+    let error = send_to(BState::initial(), "Mnau!", &config).err().unwrap();
+    assert!(matches!(error, BError::UnknownRobot(ref username) if username == "Mnau!"));
+    assert_eq!(error.server_response(), ServerMessage::LoginFailed);
+
+    common::logged_in_to(&config);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn config_built_in_place_rebuilds_its_authenticator() {
+    let mut config = ServerConfig { secrets: vec![ServerSecret { s: 0, c: 0 }], ..ServerConfig::default() };
+    config.build_authenticator().unwrap();
+    let (state, _) = send_to(BState::initial(), "Oompa Loompa", &config).unwrap();
+    let (_, res) = send_to(state, "0", &config).unwrap();
+    assert!(matches!(res, PRes::SendMessage(ServerMessage::Confirm(confirmation))
+        if confirmation == 41888));
+}
+
+#[test]
+fn invalid_key_tables_are_rejected() {
+    let cases = [
+        (vec!["--key", "0=1,2", "--key", "0=3,4"], "duplicate key ID 0"),
+        (vec!["--key", "0=1,2", "--key", "2=3,4"], "missing key ID 1"),
+        (vec!["--key", "1=1,2"], "missing key ID 0"),
+        (vec!["--key", "0=65536,2"], "key ID 0 is out of 0..65536"),
+        (vec!["--key", "0=1,2", "--key", "1=3,70000"], "key ID 1 is out of 0..65536"),
+    ];
+    for (args, expected) in cases {
+        let result = try_config(&args);
+        assert!(matches!(result, Err(ConfigError::InvalidKeyTable(ref reason)) if reason == expected), "{:?}", args);
+    }
+
+    let keys = try_config(&["--key", "1=3,4", "--key", "0=65535,0"]).unwrap().secrets;
+    assert_eq!((keys[0].s, keys[1].s), (65535, 3));
+}
+
+#[test]
+fn failed_reload_keeps_old_keys() {
+    let path = temp_file("keys-reload.txt", "0 = 23019 32037\n");
+    let keys = KeyFile::load(&path).unwrap();
+
+    fs::write(&path, "0 = 1 2\n0 = 3 4\n").unwrap();
+    assert!(matches!(keys.reload(true), Err(ConfigError::InvalidKeyTable(_))));
+    assert_eq!(keys.confirmations("Oompa Loompa", 0), Some((64907, 8389)));
+
+    fs::write(&path, "[keys]\n0 = 0 0\n1 = 1 1\n").unwrap();
+    assert!(keys.reload(true).unwrap());
+    assert_eq!(keys.confirmations("Oompa Loompa", 0), Some((41888, 41888)));
+    assert_eq!(keys.secrets().len(), 2);
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(keys.reload(true), Err(ConfigError::Io(..))));
+    assert_eq!(keys.secrets().len(), 2);
+}
+
+#[test]
+fn key_flags_override_key_file_from_config() {
+    let path = temp_file("keys-override.ini", "[server]\nkey_file = /nonexistent/keys.txt\n");
+    let result = try_config(&["--config", &path, "--key", "0=1,2"]);
+    fs::remove_file(&path).unwrap();
+
+    let overridden = result.unwrap();
+    assert_eq!(overridden.key_file, None);
+    assert_eq!((overridden.secrets[0].s, overridden.secrets[0].c), (1, 2));
+
+    let result = try_config(&["--key-file", "keys.txt", "--key", "0=1,2"]);
+    assert!(matches!(result, Err(ConfigError::InvalidKeyTable(_))));
+}
+
+#[test]
+fn reloaded_key_file_reaches_the_sessions() {
+    let path = temp_file("keys-config.txt", "0 = 23019 32037\n");
+    let config = common::config(&["--key-file", &path]);
+    common::logged_in_to(&config);
+
+    fs::write(&path, "0 = 0 0\n").unwrap();
+    assert!(config.authenticator.reload(true).unwrap());
+    fs::remove_file(&path).unwrap();
+
+    let (state, _) = send_to(BState::initial(), "Oompa Loompa", &config).unwrap();
+    let (_, res) = send_to(state, "0", &config).unwrap();
+    assert!(matches!(res, PRes::SendMessage(ServerMessage::Confirm(41888))));
+}
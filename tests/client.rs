@@ -0,0 +1,67 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use bobika::{ClientError, handle_server, Orient, RobotClient, ServerMessage, Shutdown};
+
+mod common;
+
+/// Serves one connection with the config and returns a client connected to it
+fn connect(args: &[&str]) -> RobotClient {
+    let config = common::config(args);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+        let shutdown = Arc::new(Shutdown::new(Duration::from_secs(1)));
+        let (stream, _) = listener.accept().unwrap();
+        handle_server(stream, &config, &mut shutdown.track());
+    });
+    RobotClient::connect(&addr, Duration::from_secs(2)).unwrap()
+}
+
+#[test]
+fn robot_logs_in_and_picks_up() {
+    let mut client = connect(&["--target", "1,2"]);
+    client.login("Oompa Loompa", 0).unwrap();
+
+    client.recharging().unwrap();
+    client.full_power().unwrap();
+    assert_eq!(common::navigate(&mut client, (-2, 3), Orient::EAST), (1, 2));
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+    let mut client = connect(&[]);
+    let error = client.login("Oompa Loompa", 7).unwrap_err();
+    assert!(matches!(error, ClientError::Rejected(ServerMessage::KeyOutOfRangeError)), "{}", error);
+
+    let mut client = connect(&["--key", "0=1,2"]);
+    let error = client.login("Oompa Loompa", 0).unwrap_err();
+    assert!(matches!(error, ClientError::ServerConfirmation { .. }), "{}", error);
+}
+
+#[test]
+fn robot_logs_in_with_hmac() {
+    let mut client = connect(&["--auth-mode", "hmac", "--hmac-key", "0=tajne"])
+        .with_hmac_keys(vec!["tajne".to_string()]);
+    client.login("Oompa Loompa", 0).unwrap();
+    assert_eq!(common::navigate(&mut client, (0, 1), Orient::SOUTH), (0, 0));
+
+    let mut client = connect(&["--auth-mode", "hmac", "--hmac-key", "0=tajne"])
+        .with_hmac_keys(vec!["jine".to_string()]);
+    let error = client.login("Oompa Loompa", 0).unwrap_err();
+    assert!(matches!(error, ClientError::Rejected(ServerMessage::LoginFailed)), "{}", error);
+}
+
+#[test]
+fn segmented_and_merged_messages() {
+    let mut client = connect(&[]).with_segments(2, Duration::from_millis(1));
+    client.login("Oompa Loompa", 1).unwrap();
+
+    client.recharging().unwrap();
+    client.hold();
+    client.full_power().unwrap();
+    assert_eq!(common::navigate(&mut client, (2, 0), Orient::WEST), (0, 0));
+}
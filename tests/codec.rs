@@ -0,0 +1,89 @@
+use bobika::{BError, FrameDecoder};
+
+/// The limit of `BState::LoginKey`, a key index and the terminator
+const KEY_MAX_LEN: usize = 5;
+
+fn frame(decoder: &mut FrameDecoder, max_len: usize) -> Option<String> {
+    decoder.next_frame(max_len).unwrap().map(|message| message.0)
+}
+
+#[test]
+fn merged_frames_are_split() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(b"OK 1 2\x07\x08OK 3 4\x07\x08");
+
+    assert_eq!(frame(&mut decoder, 12).as_deref(), Some("OK 1 2"));
+    assert_eq!(frame(&mut decoder, 12).as_deref(), Some("OK 3 4"));
+    assert_eq!(frame(&mut decoder, 12), None);
+    assert!(decoder.buffered().is_empty());
+}
+
+#[test]
+fn split_terminator_is_joined() {
+    let mut decoder = FrameDecoder::new();
+    for chunk in [&b"Oomp"[..], b"a\x07"] {
+        decoder.push(chunk);
+        assert_eq!(frame(&mut decoder, 20), None);
+    }
+    decoder.push(b"\x08Loo");
+    assert_eq!(frame(&mut decoder, 20).as_deref(), Some("Oompa"));
+    assert_eq!(decoder.buffered(), b"Loo");
+
+    // A lone \a is content, not half of the terminator
+    decoder.push(b"\x07mpa\x07\x08");
+    assert_eq!(frame(&mut decoder, 20).as_deref(), Some("Loo\x07mpa"));
+}
+
+#[test]
+fn leftover_is_checked_against_next_limit() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(b"0\x07\x08OK -10 -20\x07\x08FULL");
+
+    assert_eq!(frame(&mut decoder, KEY_MAX_LEN).as_deref(), Some("0"));
+    assert_eq!(decoder.buffered(), b"OK -10 -20\x07\x08FULL");
+
+    let mut short = FrameDecoder::new();
+    short.push(decoder.buffered());
+    assert!(matches!(short.next_frame(KEY_MAX_LEN), Err(BError::MessageToLong(_, 10))));
+
+    assert_eq!(frame(&mut decoder, 12).as_deref(), Some("OK -10 -20"));
+    assert_eq!(frame(&mut decoder, 12), None);
+    assert_eq!(decoder.buffered(), b"FULL");
+}
+
+#[test]
+fn too_long_before_terminator() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[b'a'; 18]);
+    assert_eq!(frame(&mut decoder, 20), None);
+
+    // Only the \b is missing
+    decoder.push(b"\x07");
+    assert_eq!(frame(&mut decoder, 20), None);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[b'a'; 19]);
+    let error = decoder.next_frame(20).unwrap_err();
+    assert!(matches!(error, BError::MessageToLong(ref message, 19) if message.len() == 19));
+}
+
+#[test]
+fn charging_fits_any_state() {
+    for word in ["RECHARGING", "FULL POWER"] {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&word.as_bytes()[..4]);
+        assert_eq!(frame(&mut decoder, KEY_MAX_LEN), None);
+
+        decoder.push(&word.as_bytes()[4..]);
+        decoder.push(b"\x07\x08");
+        assert_eq!(frame(&mut decoder, KEY_MAX_LEN).as_deref(), Some(word));
+    }
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(b"RECHARGING!");
+    assert!(decoder.next_frame(KEY_MAX_LEN).is_err());
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(b"REST");
+    assert!(decoder.next_frame(KEY_MAX_LEN).is_err());
+}
@@ -0,0 +1,153 @@
+// Every test file uses only a part of the helpers
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+use std::fs;
+
+use bobika::config::ConfigError;
+use bobika::{BError, BState, ClientMessage, NavigationStrategy, Orient, PRes, RobotClient, ServerConfig, ServerMessage, Step};
+
+/// Logs in as "Oompa Loompa" with key 0 of the default key table
+pub const LOGIN: [&str; 3] = ["Oompa Loompa", "0", "8389"];
+
+pub fn config(args: &[&str]) -> ServerConfig {
+    try_config(args).unwrap()
+}
+
+pub fn try_config(args: &[&str]) -> Result<ServerConfig, ConfigError> {
+    ServerConfig::from_args(args.iter().map(|arg| arg.to_string()))
+}
+
+/// A path in the temp directory unique to the test process
+pub fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("bobika-{}-{}", name, std::process::id()));
+    path.to_str().unwrap().to_string()
+}
+
+/// Writes the content into a file unique to the test and returns its path
+pub fn temp_file(name: &str, content: &str) -> String {
+    let path = temp_path(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+pub fn send(state: BState, message: &str) -> Result<(BState, PRes), BError> {
+    send_to(state, message, &ServerConfig::default())
+}
+
+pub fn send_to(state: BState, message: &str, config: &ServerConfig) -> Result<(BState, PRes), BError> {
+    state.handle_message(ClientMessage(message.to_string()), config)
+}
+
+pub fn logged_in() -> BState {
+    logged_in_to(&ServerConfig::default())
+}
+
+/// The state after a successful login, waiting for the reply to the first move
+pub fn logged_in_to(config: &ServerConfig) -> BState {
+    let mut state = BState::initial();
+    let mut res = PRes::SendMessages(Vec::new());
+    for message in LOGIN {
+        (state, res) = send_to(state, message, config).unwrap();
+    }
+    assert!(matches!(res, PRes::SendMessages(ref messages)
+        if messages == &[ServerMessage::NoProblemo, ServerMessage::Move]));
+    state
+}
+
+/// What the server sent to a robot played by `drive`
+pub struct Run {
+    /// Every command, starting with the move sent after the login
+    pub sent: Vec<ServerMessage>,
+    /// Where the robot was when it got `PickUp`
+    pub picked_up: Option<(i32, i32)>,
+    /// The error the session ended with
+    pub error: Option<BError>,
+}
+
+/// Plays a robot in the world until it picks up the message, the session fails or it gets `limit` commands
+pub fn drive(config: &ServerConfig, start: (i32, i32), orient: Orient, obstacles: &[(i32, i32)], limit: usize) -> Run {
+    let obstacles: HashSet<(i32, i32)> = obstacles.iter().copied().collect();
+    let mut state = logged_in_to(config);
+
+    let mut position = start;
+    let mut orient = orient;
+    let mut sent = vec![ServerMessage::Move];
+    let mut command = ServerMessage::Move;
+
+    while sent.len() < limit {
+        match command {
+            ServerMessage::Move if !obstacles.contains(&orient.move_in(position)) => position = orient.move_in(position),
+            ServerMessage::Left => orient = orient.left(),
+            ServerMessage::Right => orient = orient.right(),
+            _ => {}
+        }
+
+        let reply = format!("OK {} {}", position.0, position.1);
+        let (next, res) = match send_to(state, &reply, config) {
+            Ok(next) => next,
+            Err(e) => return Run { sent, picked_up: None, error: Some(e) },
+        };
+        state = next;
+        command = match res {
+            PRes::SendMessage(message) => message,
+            _ => panic!("expected a single message"),
+        };
+        sent.push(command.clone());
+        if command == ServerMessage::PickUp {
+            return Run { sent, picked_up: Some(position), error: None };
+        }
+    }
+    Run { sent, picked_up: None, error: None }
+}
+
+/// What a strategy did on its way to the target
+pub struct Walk {
+    pub visited: Vec<(i32, i32)>,
+    pub blocked: usize,
+    pub discovered: Vec<(i32, i32)>,
+}
+
+/// Follows the strategy's commands in the world until it arrives
+pub fn walk(strategy: &mut dyn NavigationStrategy, start: (i32, i32), orient: Orient, obstacles: &HashSet<(i32, i32)>) -> Walk {
+    let mut walk = Walk { visited: vec![start], blocked: 0, discovered: Vec::new() };
+    let mut position = start;
+    let mut orient = orient;
+
+    for _ in 0..200 {
+        let step = strategy.next(position).unwrap();
+        walk.discovered.extend(strategy.discovered());
+        match step {
+            Step::Arrived => return walk,
+            Step::Send(ServerMessage::Move) if obstacles.contains(&orient.move_in(position)) => walk.blocked += 1,
+            Step::Send(ServerMessage::Move) => {
+                position = orient.move_in(position);
+                walk.visited.push(position);
+            }
+            Step::Send(ServerMessage::Left) => orient = orient.left(),
+            Step::Send(ServerMessage::Right) => orient = orient.right(),
+            Step::Send(command) => panic!("unexpected command {:?}", command),
+        }
+    }
+    panic!("the strategy did not arrive");
+}
+
+/// Drives the robot on an empty grid, returns the picked up position
+pub fn navigate(client: &mut RobotClient, mut position: (i32, i32), mut orient: Orient) -> (i32, i32) {
+    let mut picked_up = None;
+    loop {
+        match client.next_command().unwrap() {
+            ServerMessage::Move => position = orient.move_in(position),
+            ServerMessage::Left => orient = orient.left(),
+            ServerMessage::Right => orient = orient.right(),
+            ServerMessage::PickUp => {
+                picked_up = Some(position);
+                client.send_message("Tajny vzkaz").unwrap();
+                continue;
+            }
+            ServerMessage::Logout => return picked_up.unwrap(),
+            command => panic!("unexpected command {:?}", command),
+        }
+        client.report(position).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::time::Duration;
+
+use bobika::config::{ConfigError, Runtime};
+use bobika::{Mission, Strategy};
+
+mod common;
+
+use common::{config, temp_file, try_config};
+
+const FILE: &str = "\
+# bobika.ini
+[server]
+host = 0.0.0.0
+port = 4000
+timeout = 2
+refill_timeout = 7.5
+strategy = axis
+step_budget = 50
+map = mars
+
+[keys]
+0 = 1 2
+1 = 3 4
+
+[targets]
+Oompa Loompa = 4, -2
+
+[missions]
+Mnau! = 1, 1; 0, 0
+";
+
+#[test]
+fn file_sets_every_section() {
+    let path = temp_file("full.ini", FILE);
+    let config = config(&["--config", &path]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.addr(), "0.0.0.0:4000");
+    assert_eq!(config.timeout_normal, Duration::from_secs(2));
+    assert_eq!(config.timeout_refilling, Duration::from_millis(7500));
+    assert_eq!(config.strategy, Strategy::Axis);
+    assert_eq!(config.step_budget, 50);
+    assert_eq!(config.map.as_deref(), Some("mars"));
+    assert_eq!(config.runtime, Runtime::Threads);
+
+    let keys: Vec<_> = config.secrets.iter().map(|secret| (secret.s, secret.c)).collect();
+    assert_eq!(keys, vec![(1, 2), (3, 4)]);
+    assert_eq!(config.mission_for("Oompa Loompa"), Mission::single((4, -2)));
+    assert_eq!(config.mission_for("Mnau!"), Mission { waypoints: vec![(1, 1), (0, 0)] });
+}
+
+#[test]
+fn flags_override_the_file() {
+    let path = temp_file("override.ini", FILE);
+    let config = config(&["--port", "5000", "--config", &path, "--map", "off", "--key", "0=5,6", "--strategy=planner"]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.addr(), "0.0.0.0:5000");
+    assert_eq!(config.map, None);
+    assert_eq!(config.strategy, Strategy::Planner);
+    assert_eq!(config.secrets.len(), 1);
+    assert_eq!((config.secrets[0].s, config.secrets[0].c), (5, 6));
+    assert_eq!(config.step_budget, 50);
+}
+
+#[test]
+fn unknown_keys_and_sections_are_rejected() {
+    let cases = [
+        ("[server]\nport = 1\ncolour = red\n", "unknown key"),
+        ("[server]\nport = 1\n[robots]\nx = 1\n", "unknown section"),
+        ("[server]\nport\n", "missing value"),
+    ];
+    for (content, name) in cases {
+        let path = temp_file("unknown.ini", content);
+        let result = try_config(&["--config", &path]);
+        fs::remove_file(&path).unwrap();
+
+        match (name, result) {
+            ("unknown key", Err(ConfigError::UnknownFlag(key))) => assert_eq!(key, "colour"),
+            ("unknown section", Err(ConfigError::Syntax { line, .. })) => assert_eq!(line, 3),
+            ("missing value", Err(ConfigError::Syntax { line, .. })) => assert_eq!(line, 2),
+            (name, result) => panic!("{}: {:?}", name, result.err()),
+        }
+    }
+
+    assert!(matches!(try_config(&["--colour", "red"]), Err(ConfigError::UnknownFlag(_))));
+    assert!(matches!(try_config(&["--port"]), Err(ConfigError::MissingValue(_))));
+    assert!(matches!(try_config(&["--config", "/nonexistent/bobika.ini"]), Err(ConfigError::Io(..))));
+}
+
+#[test]
+fn invalid_key_table_in_the_file() {
+    let cases = [
+        ("0 = 1 2\n0 = 3 4\n", "duplicate key ID 0"),
+        ("0 = 1 2\n1 = 3 65536\n", "key ID 1 is out of 0..65536"),
+        ("1 = 1 2\n", "missing key ID 0"),
+    ];
+    for (keys, expected) in cases {
+        let path = temp_file("keys.ini", &format!("[keys]\n{}", keys));
+        let result = try_config(&["--config", &path]);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::InvalidKeyTable(ref reason)) if reason == expected), "{:?}", keys);
+    }
+
+    let path = temp_file("keys-value.ini", "[keys]\n0 = 1\n");
+    let result = try_config(&["--config", &path]);
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(ConfigError::InvalidValue { ref key, .. }) if key == "key 0"));
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    let cases = [
+        ("port", "70000"),
+        ("timeout", "0"),
+        ("timeout", "-1"),
+        ("step_budget", "0"),
+        ("max_sessions", "x"),
+        ("map", "two words"),
+        ("log_level", "loud"),
+    ];
+    for (key, value) in cases {
+        let path = temp_file("values.ini", &format!("{} = {}\n", key, value));
+        let result = try_config(&["--config", &path]);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::InvalidValue { key: ref k, value: ref v })
+            if k == &key.replace('_', "-") && v == value), "{} = {}", key, value);
+    }
+}
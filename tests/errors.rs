@@ -0,0 +1,65 @@
+use bobika::{BError, BState, FrameDecoder, ServerMessage};
+
+mod common;
+
+use common::{logged_in, send};
+
+#[test]
+fn invalid_utf8_is_syntax_error() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[0xff, 0xfe, b'a', 7, 8]);
+
+    let error = decoder.next_frame(20).unwrap_err();
+    assert!(matches!(error, BError::InvalidUtf8(ref bytes) if bytes == &[0xff, 0xfe, b'a']));
+    assert!(error.should_send());
+    assert_eq!(error.server_response(), ServerMessage::SyntaxError);
+}
+
+#[test]
+fn impossible_move_is_logic_error() {
+    let (state, _) = send(logged_in(), "OK 1 0").unwrap();
+
+    let error = send(state, "OK 3 0").err().unwrap();
+    assert!(matches!(error, BError::ImpossibleMove { from: (1, 0), to: (3, 0) }));
+    assert_eq!(error.server_response(), ServerMessage::LogicError);
+}
+
+#[test]
+fn diagonal_move_is_logic_error() {
+    let (state, _) = send(logged_in(), "OK 1 0").unwrap();
+
+    let error = send(state, "OK 2 1").err().unwrap();
+    assert!(matches!(error, BError::ImpossibleMove { .. }));
+}
+
+#[test]
+fn negative_confirmation_is_login_failure() {
+    let (state, _) = send(BState::initial(), "Oompa Loompa").unwrap();
+    let (state, _) = send(state, "0").unwrap();
+
+    let error = send(state, "-8389").err().unwrap();
+    assert!(matches!(error, BError::InvalidConfirmation(-8389)));
+    assert_eq!(error.server_response(), ServerMessage::LoginFailed);
+}
+
+#[test]
+fn short_position_report_is_syntax_error() {
+    for message in ["OK", "", "Ž", "ŽŽŽ"] {
+        let error = send(logged_in(), message).err().unwrap();
+        assert_eq!(error.server_response(), ServerMessage::SyntaxError, "message {:?}", message);
+    }
+}
+
+#[test]
+fn write_failure_is_not_answered() {
+    let error = BError::WriteFailed(std::io::ErrorKind::BrokenPipe.into());
+    assert!(!error.should_send());
+    assert_eq!(error.server_response(), ServerMessage::Empty);
+}
+
+#[test]
+fn io_failure_is_not_answered() {
+    let error = BError::Io(std::io::ErrorKind::InvalidInput.into());
+    assert!(!error.should_send());
+    assert_eq!(error.server_response(), ServerMessage::Empty);
+}
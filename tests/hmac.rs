@@ -0,0 +1,97 @@
+use std::fs;
+
+use bobika::config::ConfigError;
+use bobika::hmac_auth::{self, ANSWER_LEN};
+use bobika::{BError, BState, PRes, ServerConfig, ServerMessage};
+
+mod common;
+
+use common::{config, send_to, temp_file, try_config};
+
+const NONCE: &str = "0123456789abcdef";
+
+#[test]
+fn answer_is_verified() {
+    let answer = hmac_auth::answer("tajne", "Oompa Loompa", NONCE);
+    assert_eq!(answer.len(), ANSWER_LEN);
+    assert!(answer.bytes().all(|byte| byte.is_ascii_digit() || byte.is_ascii_lowercase()));
+
+    assert!(hmac_auth::verify("tajne", "Oompa Loompa", NONCE, &answer));
+    assert!(hmac_auth::verify("tajne", "Oompa Loompa", NONCE, &answer.to_uppercase()));
+}
+
+#[test]
+fn wrong_answers_are_rejected() {
+    let answer = hmac_auth::answer("tajne", "Oompa Loompa", NONCE);
+
+    assert!(!hmac_auth::verify("jine", "Oompa Loompa", NONCE, &answer));
+    assert!(!hmac_auth::verify("tajne", "Mnau!", NONCE, &answer));
+    assert!(!hmac_auth::verify("tajne", "Oompa Loompa", "fedcba9876543210", &answer));
+
+    let wrong_length = [&answer[..ANSWER_LEN - 2], &format!("{}00", answer), ""];
+    for wrong in wrong_length {
+        assert!(!hmac_auth::verify("tajne", "Oompa Loompa", NONCE, wrong), "{:?}", wrong);
+    }
+
+    let not_hex = [format!("{}g", &answer[1..]), format!("+{}", &answer[1..]), "é".repeat(ANSWER_LEN / 2)];
+    for wrong in not_hex {
+        assert_eq!(wrong.len(), ANSWER_LEN);
+        assert!(!hmac_auth::verify("tajne", "Oompa Loompa", NONCE, &wrong), "{:?}", wrong);
+    }
+}
+
+#[test]
+fn nonces_are_fresh() {
+    let nonce = hmac_auth::nonce().unwrap();
+    assert_eq!(nonce.len(), NONCE.len());
+    assert_ne!(nonce, hmac_auth::nonce().unwrap());
+}
+
+/// Sends the username and the key index, returns the state and the nonce
+fn challenge(config: &ServerConfig, username: &str, key: &str) -> Result<(BState, String), BError> {
+    let (state, _) = send_to(BState::initial(), username, config)?;
+    let (state, res) = send_to(state, key, config)?;
+    match res {
+        PRes::SendMessage(ServerMessage::Nonce(nonce)) => Ok((state, nonce)),
+        _ => panic!("expected a nonce"),
+    }
+}
+
+#[test]
+fn login_checks_the_answer() {
+    let config = config(&["--auth-mode", "hmac", "--hmac-key", "0=tajne", "--hmac-key", "1=jine"]);
+
+    let (state, nonce) = challenge(&config, "Oompa Loompa", "1").unwrap();
+    let answer = hmac_auth::answer("jine", "Oompa Loompa", &nonce);
+    let (_, res) = send_to(state, &answer, &config).unwrap();
+    assert!(matches!(res, PRes::SendMessages(ref messages) if messages[0] == ServerMessage::NoProblemo));
+
+    let (state, nonce) = challenge(&config, "Oompa Loompa", "0").unwrap();
+    let answer = hmac_auth::answer("jine", "Oompa Loompa", &nonce);
+    let error = send_to(state, &answer, &config).err().unwrap();
+    assert!(matches!(error, BError::AnswerMismatch));
+    assert_eq!(error.server_response(), ServerMessage::LoginFailed);
+
+    let error = challenge(&config, "Oompa Loompa", "2").err().unwrap();
+    assert!(matches!(error, BError::InvalidKeyIndex(2)));
+}
+
+#[test]
+fn allow_list_applies_to_hmac() {
+    let path = temp_file("hmac-allowed.txt", "Oompa Loompa\n");
+    let config = config(&["--auth-mode", "hmac", "--hmac-key", "0=tajne", "--allow-list", &path]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(challenge(&config, "Oompa Loompa", "0").is_ok());
+    let error = challenge(&config, "Mnau!", "0").err().unwrap();
+    assert!(matches!(error, BError::UnknownRobot(_)));
+}
+
+#[test]
+fn classic_key_files_are_refused_with_hmac() {
+    for option in ["--robot-keys", "--key-file"] {
+        let args = ["--auth-mode", "hmac", "--hmac-key", "0=tajne", option, "keys.txt"];
+        let result = try_config(&args);
+        assert!(matches!(result, Err(ConfigError::InvalidKeyTable(ref reason)) if reason.contains("classic")), "{}", option);
+    }
+}
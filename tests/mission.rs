@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use bobika::{Orient, PRes, ServerConfig, ServerMessage};
+
+mod common;
+
+use common::{config, send_to};
+
+/// What the server did during a whole mission
+struct Played {
+    /// Where the robot was at every `PickUp`
+    picked_up: Vec<(i32, i32)>,
+    /// The command sent right after every picked up message
+    after_pick_up: Vec<ServerMessage>,
+    /// The messages and the response of `PRes::Finish`
+    finished: (Vec<String>, ServerMessage),
+}
+
+/// Plays a robot answering every pick up with "vzkaz N" until the session finishes
+fn play(config: &ServerConfig, start: (i32, i32), orient: Orient, obstacles: &[(i32, i32)]) -> Played {
+    let obstacles: HashSet<(i32, i32)> = obstacles.iter().copied().collect();
+    let mut state = common::logged_in_to(config);
+    let mut position = start;
+    let mut orient = orient;
+    let mut command = ServerMessage::Move;
+    let mut picked_up = Vec::new();
+    let mut after_pick_up = Vec::new();
+
+    for _ in 0..500 {
+        let reply = match command {
+            ServerMessage::Move => {
+                if !obstacles.contains(&orient.move_in(position)) {
+                    position = orient.move_in(position);
+                }
+                format!("OK {} {}", position.0, position.1)
+            }
+            ServerMessage::Left => {
+                orient = orient.left();
+                format!("OK {} {}", position.0, position.1)
+            }
+            ServerMessage::Right => {
+                orient = orient.right();
+                format!("OK {} {}", position.0, position.1)
+            }
+            ServerMessage::PickUp => {
+                picked_up.push(position);
+                format!("vzkaz {}", picked_up.len())
+            }
+            command => panic!("unexpected command {:?}", command),
+        };
+
+        let (next, res) = send_to(state, &reply, config).unwrap();
+        state = next;
+        let previous = command;
+        command = match res {
+            PRes::SendMessage(message) => message,
+            PRes::Finish(messages, response, _) => {
+                return Played { picked_up, after_pick_up, finished: (messages, response) };
+            }
+            _ => panic!("expected a single message or the finish"),
+        };
+        if previous == ServerMessage::PickUp {
+            after_pick_up.push(command.clone());
+        }
+    }
+    panic!("the mission did not finish");
+}
+
+fn messages(count: usize) -> Vec<String> {
+    (1..=count).map(|n| format!("vzkaz {}", n)).collect()
+}
+
+#[test]
+fn waypoints_are_visited_in_order() {
+    let config = config(&["--mission", "2,3; -4,1; 0,0"]);
+    let played = play(&config, (5, 5), Orient::WEST, &[(1, 3), (-2, 1)]);
+
+    assert_eq!(played.picked_up, vec![(2, 3), (-4, 1), (0, 0)]);
+    assert_eq!(played.finished, (messages(3), ServerMessage::Logout));
+}
+
+#[test]
+fn every_strategy_completes_a_mission() {
+    for strategy in ["planner", "axis"] {
+        let config = config(&["--mission", "-3,0; 3,0", "--strategy", strategy]);
+        let played = play(&config, (0, 4), Orient::SOUTH, &[(0, 0)]);
+
+        assert_eq!(played.picked_up, vec![(-3, 0), (3, 0)], "{}", strategy);
+        assert_eq!(played.finished, (messages(2), ServerMessage::Logout), "{}", strategy);
+    }
+}
+
+#[test]
+fn user_mission_replaces_the_target() {
+    let config = config(&["--target", "9,9", "--user-mission", "Oompa Loompa=1,0;0,1"]);
+    let played = play(&config, (1, 3), Orient::NORTH, &[]);
+
+    assert_eq!(played.picked_up, vec![(1, 0), (0, 1)]);
+    assert_eq!(played.finished.0, messages(2));
+}
+
+#[test]
+fn starting_on_a_waypoint_probes_the_orientation() {
+    let config = config(&["--mission", "0,0; 0,3"]);
+    let played = play(&config, (0, 1), Orient::SOUTH, &[]);
+
+    // The first move ends on the waypoint, the orientation is not known yet
+    assert_eq!(played.picked_up, vec![(0, 0), (0, 3)]);
+    assert_eq!(played.after_pick_up[0], ServerMessage::Move);
+    assert_eq!(played.finished, (messages(2), ServerMessage::Logout));
+}
+
+#[test]
+fn repeated_waypoint_picks_up_again() {
+    let config = config(&["--mission", "1,1; 1,1; 2,1"]);
+    let played = play(&config, (1, 2), Orient::SOUTH, &[]);
+
+    assert_eq!(played.picked_up, vec![(1, 1), (1, 1), (2, 1)]);
+    assert_eq!(played.after_pick_up[0], ServerMessage::PickUp);
+    assert_eq!(played.finished, (messages(3), ServerMessage::Logout));
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fs;
+
+use bobika::obstacles::{self, ObstacleStore};
+use bobika::{AxisStrategy, Orient, Planner, ServerConfig};
+
+mod common;
+
+use common::{send_to, temp_file, temp_path, walk};
+
+#[test]
+fn store_round_trip() {
+    let store = ObstacleStore::new();
+    store.record("mars", &[(1, 2), (-3, 4)]);
+    store.record("mars", &[(1, 2)]);
+    store.record("venus", &[(0, -1)]);
+
+    let path = temp_path("obstacles.txt");
+    store.save(&path).unwrap();
+    let loaded = ObstacleStore::new();
+    loaded.load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.known("mars"), HashSet::from([(1, 2), (-3, 4)]));
+    assert_eq!(loaded.known("venus"), HashSet::from([(0, -1)]));
+    assert!(loaded.known("pluto").is_empty());
+}
+
+#[test]
+fn store_load_checks_lines() {
+    let store = ObstacleStore::new();
+    store.load(&temp_path("missing.txt")).unwrap();
+    assert!(store.known("mars").is_empty());
+
+    for content in ["mars 1\n", "mars 1 2 3\n", "mars x 2\n"] {
+        let path = temp_file("broken.txt", &format!("# map x y\nmars 0 0\n\n{}", content));
+        let error = ObstacleStore::new().load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.to_string().starts_with("line 4:"), "{}", error);
+    }
+}
+
+#[test]
+fn planner_goes_around_hints() {
+    let hints = HashSet::from([(0, 3), (0, 2), (-1, 2), (1, 2)]);
+    let mut planner = Planner::new((0, 5), Orient::SOUTH, (0, 0), hints.clone());
+
+    let walk = walk(&mut planner, (0, 5), Orient::SOUTH, &hints);
+    assert_eq!(walk.blocked, 0);
+    assert_eq!(walk.visited.last(), Some(&(0, 0)));
+    assert!(walk.discovered.is_empty(), "known obstacles are not shared again");
+}
+
+#[test]
+fn planner_ignores_hints_blocking_every_path() {
+    let hints = HashSet::from([(0, 1), (1, 0), (0, -1), (-1, 0)]);
+    let mut planner = Planner::new((0, 3), Orient::SOUTH, (0, 0), hints);
+
+    // The map is stale, the target is open
+    let walk = walk(&mut planner, (0, 3), Orient::SOUTH, &HashSet::new());
+    assert_eq!(walk.visited, vec![(0, 3), (0, 2), (0, 1), (0, 0)]);
+}
+
+#[test]
+fn axis_shares_and_uses_the_map() {
+    let obstacles = HashSet::from([(5, 2), (0, 2)]);
+
+    let mut axis = AxisStrategy::new((5, 4), Orient::SOUTH, (0, 0), HashSet::new());
+    let first = walk(&mut axis, (5, 4), Orient::SOUTH, &obstacles);
+    assert_eq!(first.visited.last(), Some(&(0, 0)));
+    assert_eq!(first.discovered.iter().copied().collect::<HashSet<_>>(), obstacles);
+    assert_eq!(first.blocked, 2);
+
+    let mut axis = AxisStrategy::new((5, 4), Orient::SOUTH, (0, 0), obstacles.clone());
+    let second = walk(&mut axis, (5, 4), Orient::SOUTH, &obstacles);
+    assert_eq!(second.visited.last(), Some(&(0, 0)));
+    assert_eq!(second.blocked, 0);
+    assert!(second.discovered.is_empty());
+}
+
+#[test]
+fn probing_obstacles_are_shared() {
+    let map = format!("probing-{}", std::process::id());
+    let config = ServerConfig { map: Some(map.clone()), ..ServerConfig::default() };
+
+    // Facing west and north is blocked, the move after the second right turn goes east
+    let mut state = common::logged_in_to(&config);
+    for reply in ["OK 5 5", "OK 5 5", "OK 5 5", "OK 5 5", "OK 5 5", "OK 6 5"] {
+        state = send_to(state, reply, &config).unwrap().0;
+    }
+    assert_eq!(obstacles::shared().known(&map), HashSet::from([(4, 5), (5, 6)]));
+}
+
+#[test]
+fn store_saves_only_changes() {
+    let path = temp_path("changes.txt");
+    let store = ObstacleStore::new();
+    assert!(!store.save_changes(&path).unwrap());
+
+    store.record("mars", &[(1, 2)]);
+    assert!(store.save_changes(&path).unwrap());
+    assert!(!store.save_changes(&path).unwrap());
+
+    // Known obstacles are no change
+    store.record("mars", &[(1, 2)]);
+    assert!(!store.save_changes(&path).unwrap());
+    store.record("mars", &[(1, 2), (3, 4)]);
+    assert!(store.save_changes(&path).unwrap());
+
+    let loaded = ObstacleStore::new();
+    loaded.load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.known("mars"), HashSet::from([(1, 2), (3, 4)]));
+    assert!(!loaded.save_changes(&path).unwrap(), "loading is no change");
+}
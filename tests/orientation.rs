@@ -0,0 +1,112 @@
+use bobika::{BError, BState, Orient, PRes, ServerConfig, ServerMessage, Strategy};
+
+mod common;
+
+use common::{logged_in, send};
+
+/// Replies with the positions and returns the state with the last command sent
+fn reply(positions: &[(i32, i32)]) -> (BState, ServerMessage) {
+    let mut state = logged_in();
+    let mut command = ServerMessage::Move;
+    for (x, y) in positions {
+        let (next, res) = send(state, &format!("OK {} {}", x, y)).unwrap();
+        command = match res {
+            PRes::SendMessage(message) => message,
+            _ => panic!("expected a single message"),
+        };
+        state = next;
+    }
+    (state, command)
+}
+
+#[test]
+fn first_move_onto_target_picks_up() {
+    let (_, command) = reply(&[(0, 0)]);
+    assert_eq!(command, ServerMessage::PickUp);
+}
+
+#[test]
+fn starting_at_target_comes_back() {
+    for strategy in [Strategy::Planner, Strategy::Axis] {
+        let config = ServerConfig { strategy, ..ServerConfig::default() };
+        for orient in Orient::ALL {
+            // The first move leaves the target, the second tells the orientation
+            let run = common::drive(&config, (0, 0), orient, &[], 20);
+            assert_eq!(run.picked_up, Some((0, 0)), "{:?} facing {:?}", strategy, orient);
+            assert!(run.sent.len() <= 7, "{:?} facing {:?}: {:?}", strategy, orient, run.sent);
+        }
+    }
+}
+
+#[test]
+fn second_move_onto_target_picks_up() {
+    let (_, command) = reply(&[(1, 0), (0, 0)]);
+    assert_eq!(command, ServerMessage::PickUp);
+}
+
+#[test]
+fn second_move_tells_orientation() {
+    // Moving west from [3, 4], already heading the right way
+    let (_, command) = reply(&[(3, 4)]);
+    assert_eq!(command, ServerMessage::Move);
+    let (_, command) = reply(&[(3, 4), (2, 4)]);
+    assert_eq!(command, ServerMessage::Move);
+}
+
+#[test]
+fn second_move_away_from_target_turns() {
+    let (_, command) = reply(&[(3, 4), (4, 4)]);
+    assert!(matches!(command, ServerMessage::Left | ServerMessage::Right));
+}
+
+#[test]
+fn starting_on_axis() {
+    // Heading south along the y axis
+    let (_, command) = reply(&[(0, 5), (0, 4)]);
+    assert_eq!(command, ServerMessage::Move);
+
+    // Heading east, off the x axis
+    let (_, command) = reply(&[(-5, 0), (-4, 0)]);
+    assert_eq!(command, ServerMessage::Move);
+}
+
+#[test]
+fn leaving_axis_turns_back() {
+    let (_, command) = reply(&[(0, 5), (1, 5)]);
+    assert!(matches!(command, ServerMessage::Left | ServerMessage::Right));
+}
+
+#[test]
+fn blocked_move_turns_and_probes_again() {
+    let (_, command) = reply(&[(3, 4), (3, 4)]);
+    assert_eq!(command, ServerMessage::Right);
+    let (_, command) = reply(&[(3, 4), (3, 4), (3, 4)]);
+    assert_eq!(command, ServerMessage::Move);
+    let (_, command) = reply(&[(3, 4), (3, 4), (3, 4), (2, 4)]);
+    assert_eq!(command, ServerMessage::Move);
+}
+
+#[test]
+fn probing_into_target_picks_up() {
+    let (_, command) = reply(&[(1, 0), (1, 0), (1, 0), (0, 0)]);
+    assert_eq!(command, ServerMessage::PickUp);
+}
+
+#[test]
+fn probing_is_bounded() {
+    let mut state = logged_in();
+    let mut commands = 1;
+    let error = loop {
+        match send(state, "OK 3 4") {
+            Ok((next, _)) => {
+                state = next;
+                commands += 1;
+            }
+            Err(e) => break e,
+        }
+        assert!(commands <= 8, "too many probing commands");
+    };
+
+    assert!(matches!(error, BError::Enclosed((3, 4))));
+    assert_eq!(error.server_response(), ServerMessage::Logout);
+}
@@ -0,0 +1,35 @@
+use std::collections::HashSet;
+
+use bobika::{NavigationStrategy, Orient, Planner, ServerMessage, Step};
+
+mod common;
+
+use common::walk;
+
+#[test]
+fn blocked_move_replans() {
+    let mut planner = Planner::new((0, 5), Orient::SOUTH, (0, 0), HashSet::new());
+    assert_eq!(planner.next((0, 5)).unwrap(), Step::Send(ServerMessage::Move));
+
+    // The move did not change the position
+    let step = planner.next((0, 5)).unwrap();
+    assert!(matches!(step, Step::Send(ServerMessage::Left | ServerMessage::Right)), "{:?}", step);
+    assert_eq!(planner.obstacles(), &HashSet::from([(0, 4)]));
+    assert_eq!(planner.discovered(), vec![(0, 4)]);
+    assert_eq!(planner.discovered(), vec![]);
+}
+
+#[test]
+fn walls_found_on_the_way_are_avoided() {
+    let wall = HashSet::from([(-1, 2), (0, 2), (1, 2), (2, 2), (3, 2)]);
+    let mut planner = Planner::new((0, 5), Orient::SOUTH, (0, 0), HashSet::new());
+
+    let walk = walk(&mut planner, (0, 5), Orient::SOUTH, &wall);
+    assert_eq!(walk.visited.last(), Some(&(0, 0)));
+    assert!(walk.blocked > 0);
+    // Every blocked move is a new wall cell, none is tried twice
+    let discovered: HashSet<_> = walk.discovered.iter().copied().collect();
+    assert_eq!(discovered.len(), walk.discovered.len());
+    assert_eq!(walk.blocked, walk.discovered.len());
+    assert!(discovered.is_subset(&wall));
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use bobika::{Orient, ServerConfig, ServerMessage, Strategy};
+
+fn is_turn(message: &ServerMessage) -> bool {
+    matches!(message, ServerMessage::Left | ServerMessage::Right)
+}
+
+#[test]
+fn rotation_is_shortest() {
+    for from in Orient::ALL {
+        for to in Orient::ALL {
+            let turns = from.rotate_to(to);
+            assert!(turns.len() <= 2, "{:?} -> {:?}: {:?}", from, to, turns);
+
+            let facing = turns.iter().fold(from, |orient, turn| match turn {
+                ServerMessage::Left => orient.left(),
+                _ => orient.right(),
+            });
+            assert_eq!(facing, to);
+        }
+    }
+}
+
+#[test]
+fn left_and_right_are_inverse() {
+    for orient in Orient::ALL {
+        assert_eq!(orient.left().right(), orient);
+        assert_eq!(orient.right().left(), orient);
+        assert_eq!(orient.rotate_to(orient.left()), vec![ServerMessage::Left]);
+        assert_eq!(orient.rotate_to(orient.right()), vec![ServerMessage::Right]);
+    }
+}
+
+/// Small deterministic generator, so every run checks the same worlds
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, range: i32) -> i32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % range as u64) as i32
+    }
+}
+
+#[test]
+fn never_three_turns_in_a_row() {
+    let mut rng = Lcg(42);
+
+    for strategy in [Strategy::Planner, Strategy::Axis] {
+        let config = ServerConfig { strategy, ..ServerConfig::default() };
+
+        for _ in 0..300 {
+            let start = (rng.next(15) - 7, rng.next(15) - 7);
+            let orient = Orient::ALL[rng.next(4) as usize];
+            let obstacles: Vec<(i32, i32)> = (0..rng.next(12))
+                .map(|_| (rng.next(15) - 7, rng.next(15) - 7))
+                .filter(|cell| *cell != start && *cell != (0, 0))
+                .collect();
+
+            let sent = common::drive(&config, start, orient, &obstacles, 300).sent;
+            let turns = sent.windows(3).find(|window| window.iter().all(is_turn));
+            assert!(turns.is_none(), "{:?} from {:?} {:?} with {:?}: {:?}", strategy, start, orient, obstacles, sent);
+
+            if strategy == Strategy::Planner && obstacles.iter().all(|cell| cell.0.abs() + cell.1.abs() > 1) {
+                assert_eq!(sent.last(), Some(&ServerMessage::PickUp), "{:?} {:?} {:?}", start, orient, obstacles);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use bobika::{BState, Shutdown, Verdict};
+
+mod common;
+
+#[test]
+fn nothing_changes_until_requested() {
+    let shutdown = Shutdown::new(Duration::ZERO);
+    assert_eq!(shutdown.verdict(&BState::initial()), Verdict::Continue);
+    assert_eq!(shutdown.verdict(&common::logged_in()), Verdict::Continue);
+}
+
+#[test]
+fn logging_in_sessions_are_closed() {
+    let shutdown = Shutdown::new(Duration::from_secs(3600));
+    shutdown.request();
+
+    let mut state = BState::initial();
+    for message in &common::LOGIN[..2] {
+        assert_eq!(shutdown.verdict(&state), Verdict::Close);
+        state = common::send(state, message).unwrap().0;
+    }
+    assert_eq!(shutdown.verdict(&state), Verdict::Close);
+}
+
+#[test]
+fn navigating_sessions_get_the_grace_period() {
+    let shutdown = Shutdown::new(Duration::from_millis(200));
+    shutdown.flag().store(true, Ordering::SeqCst);
+    let state = common::logged_in();
+    assert!(state.is_navigating());
+
+    // The grace period starts with the first verdict after the request
+    assert_eq!(shutdown.verdict(&state), Verdict::Continue);
+    let (recharging, _) = common::send(state, "RECHARGING").unwrap();
+    assert_eq!(shutdown.verdict(&recharging), Verdict::Continue);
+
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(shutdown.verdict(&recharging), Verdict::Logout);
+    assert_eq!(shutdown.verdict(&common::logged_in()), Verdict::Logout);
+    assert_eq!(shutdown.verdict(&BState::initial()), Verdict::Close);
+}
+
+#[test]
+fn guards_count_drained_and_aborted_sessions() {
+    let shutdown = Arc::new(Shutdown::new(Duration::ZERO));
+
+    // Sessions finished before the request are not counted
+    drop(shutdown.track());
+    let drained = shutdown.track();
+    let mut aborted = shutdown.track();
+    let mut closed = shutdown.track();
+    assert_eq!(shutdown.active(), 3);
+
+    shutdown.request();
+    assert_eq!(closed.verdict(&BState::initial()), Verdict::Close);
+    closed.abort();
+    aborted.abort();
+    drop(drained);
+    drop(aborted);
+    assert_eq!(shutdown.report(), (1, 1));
+    assert_eq!(shutdown.active(), 1);
+
+    let waiting = {
+        let shutdown = Arc::clone(&shutdown);
+        thread::spawn(move || shutdown.wait_idle())
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!waiting.is_finished());
+    drop(closed);
+    waiting.join().unwrap();
+
+    assert_eq!(shutdown.report(), (1, 2));
+    assert_eq!(shutdown.active(), 0);
+}
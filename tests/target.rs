@@ -0,0 +1,77 @@
+use std::fs;
+
+use bobika::{Mission, Orient, ServerConfig, Strategy};
+
+mod common;
+
+use common::{config, temp_file, try_config};
+
+/// Plays a robot logging in as "Oompa Loompa" and returns where it picked up the message
+fn pick_up(config: &ServerConfig, start: (i32, i32), orient: Orient, obstacles: &[(i32, i32)]) -> Option<(i32, i32)> {
+    common::drive(config, start, orient, obstacles, 200).picked_up
+}
+
+#[test]
+fn target_defaults_to_origin() {
+    let config = config(&[]);
+    assert_eq!(config.mission_for("Oompa Loompa"), Mission::single((0, 0)));
+}
+
+#[test]
+fn target_from_flags() {
+    let config = config(&["--target", "4,-2", "--user-target", "Mnau!=-7,3"]);
+    assert_eq!(config.mission_for("Oompa Loompa"), Mission::single((4, -2)));
+    assert_eq!(config.mission_for("Mnau!"), Mission::single((-7, 3)));
+}
+
+#[test]
+fn target_from_file() {
+    let path = temp_file("target.ini", "[server]\ntarget = 10 10\n\n[targets]\nOompa Loompa = -3, 8\n");
+    let config = config(&["--config", &path]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(config.target, (10, 10));
+    assert_eq!(config.mission_for("Oompa Loompa"), Mission::single((-3, 8)));
+}
+
+#[test]
+fn invalid_target_is_rejected() {
+    for value in ["1", "1,2,3", "a,b", ""] {
+        assert!(try_config(&["--target", value]).is_err(), "{:?}", value);
+    }
+}
+
+#[test]
+fn valid_orientation_is_relative_to_target() {
+    assert!(Orient::EAST.is_valid_for((2, 5), (6, 5)));
+    assert!(!Orient::WEST.is_valid_for((2, 5), (6, 5)));
+    assert!(Orient::SOUTH.is_valid_for((6, 9), (6, 5)));
+    assert!(!Orient::NORTH.is_valid_for((6, 9), (6, 5)));
+    assert!(!Orient::NORTH.is_valid_for((6, 5), (6, 5)));
+}
+
+#[test]
+fn strategies_reach_non_origin_target() {
+    let cases = [
+        ((0, 0), Orient::NORTH, vec![]),
+        ((9, 9), Orient::SOUTH, vec![(9, 7), (6, 8)]),
+        ((-4, 7), Orient::EAST, vec![(0, 7), (4, 8)]),
+        ((4, -3), Orient::WEST, vec![(4, 2)]),
+    ];
+
+    for strategy in [Strategy::Planner, Strategy::Axis] {
+        let mut config = config(&["--target", "4,7"]);
+        config.strategy = strategy;
+
+        for (start, orient, obstacles) in &cases {
+            let picked_up = pick_up(&config, *start, *orient, obstacles);
+            assert_eq!(picked_up, Some((4, 7)), "{:?} from {:?}", strategy, start);
+        }
+    }
+}
+
+#[test]
+fn starting_at_user_target_picks_up() {
+    let config = config(&["--target", "4,7", "--user-target", "Oompa Loompa=-5,-5"]);
+    assert_eq!(pick_up(&config, (-5, -5), Orient::NORTH, &[(-5, -4)]), Some((-5, -5)));
+    assert_eq!(pick_up(&config, (2, 1), Orient::WEST, &[]), Some((-5, -5)));
+}
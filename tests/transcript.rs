@@ -0,0 +1,157 @@
+use std::fs;
+
+use bobika::hmac_auth;
+use bobika::logger::LogContext;
+use bobika::transcript::{self, Entry, Recorder};
+use bobika::{BState, ClientMessage, PRes, ServerConfig, ServerMessage};
+
+mod common;
+
+use common::{config, temp_file, temp_path};
+
+/// A session driven through the state machine and recorded like the server does
+struct Session {
+    state: Option<BState>,
+    recorder: Recorder,
+    path: String,
+}
+
+impl Session {
+    fn new(name: &str) -> Session {
+        let dir = temp_path(name);
+        fs::create_dir_all(&dir).unwrap();
+        let context = LogContext::new(None);
+        let path = format!("{}/session-{}.log", dir, context.session);
+        Session { state: Some(BState::initial()), recorder: Recorder::create(&dir, &context).unwrap(), path }
+    }
+
+    /// Sends the client message, returns the server messages
+    fn say(&mut self, message: &str, config: &ServerConfig) -> Vec<ServerMessage> {
+        let message = ClientMessage(message.to_string());
+        self.recorder.client(&message);
+        let (next, res) = self.state.take().unwrap().handle_message(message, config).unwrap();
+        let sent = match res {
+            PRes::SendMessage(message) => vec![message],
+            PRes::SendMessages(messages) => messages,
+            PRes::UpdateTimeout(_) => vec![],
+            PRes::Finish(_, response, _) => return self.sent(vec![response]),
+        };
+        self.state = Some(next);
+        self.sent(sent)
+    }
+
+    fn sent(&mut self, messages: Vec<ServerMessage>) -> Vec<ServerMessage> {
+        for message in &messages {
+            self.recorder.server(message);
+        }
+        messages
+    }
+
+    /// Loads the transcript and removes its directory
+    fn load(self) -> Vec<transcript::Record> {
+        let records = transcript::load(&self.path).unwrap();
+        fs::remove_dir_all(std::path::Path::new(&self.path).parent().unwrap()).unwrap();
+        records
+    }
+}
+
+#[test]
+fn escape_round_trip() {
+    let cases = [
+        ("Oompa Loompa", "Oompa Loompa"),
+        ("OK 1 2\x07\x08", "OK 1 2\\x07\\x08"),
+        ("back\\slash \\x41", "back\\\\slash \\\\x41"),
+        ("two\nlines\r\t", "two\\x0alines\\x0d\\x09"),
+        ("\x7f é", "\\x7f é"),
+    ];
+    for (raw, escaped) in cases {
+        assert_eq!(transcript::escape(raw), escaped);
+        assert_eq!(transcript::unescape(escaped).as_deref(), Some(raw));
+    }
+
+    for broken in ["\\", "\\q", "\\x4", "\\xzz", "\\x+4"] {
+        assert_eq!(transcript::unescape(broken), None, "{:?}", broken);
+    }
+}
+
+#[test]
+fn load_rejects_malformed_lines() {
+    let path = temp_file("transcript-ok.log", "# bobika transcript\n0 C Oompa Loompa\n\n3 S 107 KEY REQUEST\n9 X timeout\n");
+    let records = transcript::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let entries: Vec<_> = records.into_iter().map(|record| record.entry).collect();
+    assert_eq!(entries, vec![
+        Entry::Client("Oompa Loompa".to_string()),
+        Entry::Server("107 KEY REQUEST".to_string()),
+        Entry::Closed("timeout".to_string()),
+    ]);
+
+    for line in ["x C hi", "5 Q hi", "5 C", "5", "-1 C hi", "5 C bad\\q"] {
+        let path = temp_file("transcript-broken.log", &format!("0 C Oompa Loompa\n{}\n", line));
+        let error = transcript::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.to_string(), "invalid transcript line 2", "{:?}", line);
+    }
+}
+
+#[test]
+fn recorded_session_replays_cleanly() {
+    let config = config(&[]);
+    let mut session = Session::new("record-classic");
+    for message in common::LOGIN {
+        session.say(message, &config);
+    }
+    session.say("RECHARGING", &config);
+    session.say("FULL POWER", &config);
+    assert_eq!(session.say("OK 0 2", &config), [ServerMessage::Move]);
+    assert_eq!(session.say("OK 0 1", &config), [ServerMessage::Move]);
+    assert_eq!(session.say("OK 0 0", &config), [ServerMessage::PickUp]);
+    assert_eq!(session.say("Tajny vzkaz", &config), [ServerMessage::Logout]);
+    let records = session.load();
+
+    let report = transcript::replay(&records, &config);
+    assert_eq!(report.steps, 9);
+    assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+}
+
+#[test]
+fn changed_keys_are_a_mismatch() {
+    let config = config(&[]);
+    let mut session = Session::new("record-mismatch");
+    for message in common::LOGIN {
+        session.say(message, &config);
+    }
+    let records = session.load();
+
+    let report = transcript::replay(&records, &common::config(&["--key", "0=1,2"]));
+    assert_eq!(report.steps, 3);
+    let mismatch = &report.mismatches[0];
+    assert_eq!((mismatch.step, mismatch.client.as_str()), (2, "0"));
+    assert_eq!(mismatch.expected, ["64907"]);
+    assert_eq!(report.mismatches[1].expected, ["200 OK", "102 MOVE"]);
+    assert_eq!(report.mismatches[1].actual, ["300 LOGIN FAILED"]);
+}
+
+#[test]
+fn hmac_session_replays_with_the_recorded_nonce() {
+    let config = config(&["--auth-mode", "hmac", "--hmac-key", "0=tajne"]);
+    let mut session = Session::new("record-hmac");
+    session.say("Oompa Loompa", &config);
+    let nonce = match &session.say("0", &config)[..] {
+        [ServerMessage::Nonce(nonce)] => nonce.clone(),
+        sent => panic!("expected a nonce, got {:?}", sent),
+    };
+    session.say(&hmac_auth::answer("tajne", "Oompa Loompa", &nonce), &config);
+    assert_eq!(session.say("OK 0 0", &config), [ServerMessage::PickUp]);
+    let records = session.load();
+
+    let report = transcript::replay(&records, &config);
+    assert_eq!(report.steps, 4);
+    assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+
+    // A wrong answer is still caught
+    let other = common::config(&["--auth-mode", "hmac", "--hmac-key", "0=jine"]);
+    let report = transcript::replay(&records, &other);
+    assert_eq!(report.mismatches[0].step, 3);
+    assert_eq!(report.mismatches[0].actual, ["300 LOGIN FAILED"]);
+}
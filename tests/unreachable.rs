@@ -0,0 +1,46 @@
+use bobika::{BError, Orient, ServerConfig, ServerMessage, Strategy, Unreachable};
+
+mod common;
+
+use common::drive;
+
+/// The four neighbours of the default target
+const WALLED: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+#[test]
+fn walled_target_has_no_path() {
+    let run = drive(&ServerConfig::default(), (3, 2), Orient::WEST, &WALLED, 200);
+    let error = run.error.expect("the session should fail");
+
+    assert!(matches!(error, BError::Unreachable(Unreachable::NoPath { target: (0, 0), obstacles: 4 })), "{:?}", error);
+    assert_eq!(error.server_response(), ServerMessage::Logout);
+    assert_eq!(run.picked_up, None);
+}
+
+#[test]
+fn step_budget_gives_up() {
+    for strategy in [Strategy::Planner, Strategy::Axis] {
+        let config = ServerConfig { strategy, step_budget: 10, ..ServerConfig::default() };
+        let run = drive(&config, (20, 20), Orient::NORTH, &[], 100);
+        let error = run.error.expect("the session should fail");
+
+        assert!(matches!(error, BError::Unreachable(Unreachable::StepBudget(10))), "{:?}", error);
+        assert_eq!(error.server_response(), ServerMessage::Logout);
+        assert_eq!(run.sent.len(), 10, "{:?}", strategy);
+    }
+}
+
+#[test]
+fn axis_gives_up_walled_target() {
+    let config = ServerConfig { strategy: Strategy::Axis, step_budget: 50, ..ServerConfig::default() };
+    let run = drive(&config, (3, 2), Orient::WEST, &WALLED, 100);
+    assert!(matches!(run.error, Some(BError::Unreachable(Unreachable::StepBudget(50)))), "{:?}", run.error);
+}
+
+#[test]
+fn budget_leaves_room_for_the_path() {
+    let config = ServerConfig { step_budget: 30, ..ServerConfig::default() };
+    let run = drive(&config, (10, 10), Orient::SOUTH, &[], 100);
+    assert!(run.error.is_none(), "{:?}", run.error);
+    assert_eq!(run.picked_up, Some((0, 0)));
+}
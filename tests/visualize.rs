@@ -0,0 +1,87 @@
+use std::fs;
+
+use bobika::transcript::{self, Entry};
+use bobika::visualize::Track;
+use bobika::Orient;
+
+mod common;
+
+/// Starts at [2, 1] heading west, bumps into [0, 1], picks up at [1, 0] and [0, 0]
+const TRANSCRIPT: &str = "\
+# bobika transcript session=1 peer=- started=0
+0 C Oompa Loompa
+0 S 107 KEY REQUEST
+1 C 0
+1 S 64907
+2 C 8389
+2 S 200 OK
+2 S 102 MOVE
+3 C OK 2 1
+3 S 102 MOVE
+4 C OK 1 1
+4 S 102 MOVE
+5 C RECHARGING
+6 C FULL POWER
+7 C OK 1 1
+7 S 103 TURN LEFT
+8 C OK 1 1
+8 S 102 MOVE
+9 C OK 1 0
+9 S 105 GET MESSAGE
+10 C vzkaz 1
+10 S 104 TURN RIGHT
+11 C OK 1 0
+11 S 102 MOVE
+12 C OK 0 0
+12 S 105 GET MESSAGE
+13 C vzkaz 2
+13 S 106 LOGOUT
+";
+
+fn track(content: &str) -> Track {
+    let path = common::temp_file("visualize.log", content);
+    let records = transcript::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut track = Track::new();
+    for record in &records {
+        match &record.entry {
+            Entry::Client(message) => track.client(message),
+            Entry::Server(message) => track.server(message),
+            Entry::Closed(_) => break,
+        }
+    }
+    track
+}
+
+#[test]
+fn transcript_is_tracked() {
+    let track = track(TRANSCRIPT);
+    assert_eq!(track.username.as_deref(), Some("Oompa Loompa"));
+    assert_eq!(track.path, vec![(2, 1), (1, 1), (1, 0), (0, 0)]);
+    assert_eq!(track.obstacles.iter().copied().collect::<Vec<_>>(), vec![(0, 1)]);
+    assert_eq!(track.pickups, vec![(1, 0), (0, 0)]);
+    assert_eq!(track.orient, Some(Orient::WEST));
+}
+
+#[test]
+fn grid_is_drawn_north_up() {
+    let track = track(TRANSCRIPT);
+    let expected = "\
+x 0..2, y -1..1
+.....
+.#*S.
+.<$..
+.T...
+.....
+";
+    assert_eq!(track.render(&[(1, 0), (0, 0), (0, -1)]), expected);
+}
+
+#[test]
+fn unknown_heading_and_empty_track() {
+    let track = track("0 C Oompa Loompa\n1 S 102 MOVE\n2 C OK 3 -1\n");
+    assert_eq!(track.render(&[(3, 0)]), "x 3..3, y -1..0\n...\n.T.\n.@.\n...\n");
+
+    assert_eq!(Track::new().render(&[]), "Nothing to draw, the robot never reported its position\n");
+}